@@ -0,0 +1,60 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use send_it::reader::VarReader;
+use send_it::writer::VarWriter;
+use send_it::FrameBuf;
+
+/// The number of frames read in each iteration
+const FRAMES: usize = 1000;
+/// The number of segments in each benchmarked frame
+const SEGMENTS: usize = 100;
+/// The size of each segment in a benchmarked frame
+const SEGMENT_SIZE: usize = 16;
+
+/// Encodes `FRAMES` frames of telemetry-sized segments into one stream
+fn encoded_stream() -> Vec<u8> {
+    let mut writer = VarWriter::new();
+    for _ in 0..SEGMENTS {
+        writer.add_raw(&[0xAB; SEGMENT_SIZE]);
+    }
+    let mut stream = Vec::new();
+    for _ in 0..FRAMES {
+        writer.send_without_clearing(&mut stream).unwrap();
+    }
+    stream
+}
+
+fn bench_read(c: &mut Criterion) {
+    let stream = encoded_stream();
+
+    let mut group = c.benchmark_group("read");
+    group.throughput(Throughput::Bytes(stream.len() as u64));
+
+    group.bench_function(BenchmarkId::new("in_memory", "read_data"), |b| {
+        b.iter(|| {
+            let mut fake_stream = stream.as_slice();
+            let mut reader = VarReader::new(&mut fake_stream);
+            let mut segments = 0;
+            while let Some(frame) = reader.read_data().unwrap() {
+                segments += frame.len();
+            }
+            assert_eq!(segments, FRAMES * SEGMENTS);
+        })
+    });
+    group.bench_function(BenchmarkId::new("in_memory", "read_data_into"), |b| {
+        let mut frame = FrameBuf::new();
+        b.iter(|| {
+            let mut fake_stream = stream.as_slice();
+            let mut reader = VarReader::new(&mut fake_stream);
+            let mut segments = 0;
+            while reader.read_data_into(&mut frame).unwrap() {
+                segments += frame.len();
+            }
+            assert_eq!(segments, FRAMES * SEGMENTS);
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_read);
+criterion_main!(benches);
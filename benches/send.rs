@@ -0,0 +1,121 @@
+use std::io::{IoSlice, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use send_it::writer::VarWriter;
+
+/// The number of segments in each benchmarked frame
+const SEGMENTS: usize = 100;
+/// The size of each segment in a benchmarked frame
+const SEGMENT_SIZE: usize = 16;
+
+/// A writer that discards its input, counting the write calls made on it
+/// (each of which would be a syscall on an unbuffered TcpStream)
+#[derive(Default)]
+struct CountingSink {
+    calls: usize,
+}
+
+impl Write for CountingSink {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.calls += 1;
+        Ok(buf.len())
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> std::io::Result<usize> {
+        self.calls += 1;
+        Ok(bufs.iter().map(|buf| buf.len()).sum())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// How VarWriter sent frames before vectored writes: one write per varint byte,
+/// one per segment length and one per segment
+fn send_per_field<W: Write>(segments: &[Vec<u8>], stream: &mut W) -> std::io::Result<()> {
+    let mut total_size: usize = segments.iter().map(|segment| segment.len() + 4).sum();
+    loop {
+        let mut byte = (total_size & 0x7F) as u8;
+        total_size >>= 7;
+        if total_size != 0 {
+            byte |= 0x80;
+        }
+        stream.write_all(&[byte])?;
+        if total_size == 0 {
+            break;
+        }
+    }
+    for segment in segments {
+        stream.write_all(&(segment.len() as u32).to_le_bytes())?;
+        stream.write_all(segment)?;
+    }
+    Ok(())
+}
+
+fn frame_writer(segments: &[Vec<u8>]) -> VarWriter {
+    let mut writer = VarWriter::new();
+    for segment in segments {
+        writer.add_raw(segment);
+    }
+    writer
+}
+
+/// Connects a TcpStream to a thread that drains everything sent over it
+fn drained_connection() -> TcpStream {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    stream.set_nodelay(true).unwrap();
+    std::thread::spawn(move || {
+        let (mut peer, _) = listener.accept().unwrap();
+        let mut buf = vec![0; 64 * 1024];
+        while let Ok(read) = peer.read(&mut buf) {
+            if read == 0 {
+                break;
+            }
+        }
+    });
+    stream
+}
+
+fn bench_send(c: &mut Criterion) {
+    let segments = vec![vec![0xAB; SEGMENT_SIZE]; SEGMENTS];
+    let frame_size = SEGMENTS * (SEGMENT_SIZE + 4) + 2;
+
+    let mut per_field = CountingSink::default();
+    send_per_field(&segments, &mut per_field).unwrap();
+    let mut writer = frame_writer(&segments);
+    let mut vectored = CountingSink::default();
+    writer.send_without_clearing(&mut vectored).unwrap();
+    println!(
+        "write calls per frame of {} segments: per field = {}, vectored = {}",
+        SEGMENTS, per_field.calls, vectored.calls
+    );
+
+    let mut group = c.benchmark_group("send");
+    group.throughput(Throughput::Bytes(frame_size as u64));
+
+    group.bench_function(BenchmarkId::new("counting_sink", "per_field"), |b| {
+        let mut sink = CountingSink::default();
+        b.iter(|| send_per_field(&segments, &mut sink).unwrap())
+    });
+    group.bench_function(BenchmarkId::new("counting_sink", "vectored"), |b| {
+        let mut sink = CountingSink::default();
+        b.iter(|| writer.send_without_clearing(&mut sink).unwrap())
+    });
+
+    group.bench_function(BenchmarkId::new("tcp_loopback", "per_field"), |b| {
+        let mut stream = drained_connection();
+        b.iter(|| send_per_field(&segments, &mut stream).unwrap())
+    });
+    group.bench_function(BenchmarkId::new("tcp_loopback", "vectored"), |b| {
+        let mut stream = drained_connection();
+        b.iter(|| writer.send_without_clearing(&mut stream).unwrap())
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_send);
+criterion_main!(benches);
@@ -13,15 +13,26 @@ fn main() {
         std::thread::spawn(move || {
             // create a reader for the TcpStream
             let mut reader = send_it::reader::VarReader::new(&mut stream);
-            // loop while there is incoming data
-            while let Ok(data) = reader.read_data() {
-                // convert our segments to strings in the vector
-                let readable_data = Segment::to_readable(data);
-                
-                // print out the vector of strings
-                println!("Segments from client: [{}]", readable_data.join(", "));
+            // loop until the client closes the connection
+            loop {
+                match reader.read_data() {
+                    Ok(Some(data)) => {
+                        // convert our segments to strings in the vector
+                        let readable_data = Segment::to_readable(data);
+
+                        // print out the vector of strings
+                        println!("Segments from client: [{}]", readable_data.join(", "));
+                    }
+                    Ok(None) => {
+                        println!("Connection closed!");
+                        break;
+                    }
+                    Err(err) => {
+                        println!("Connection dropped: {}", err);
+                        break;
+                    }
+                }
             }
-            println!("Connection closed!");
         });
     }
 }
\ No newline at end of file
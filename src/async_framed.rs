@@ -0,0 +1,50 @@
+use std::future::poll_fn;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::poll_io::tokio_io::{poll_read, poll_write_vectored};
+use crate::poll_reader::ReaderCore;
+use crate::poll_writer::send_frame;
+use crate::{Result, Segment};
+
+/// A stream that both sends and reads frames, owning the underlying stream.
+/// Unlike VarReader, which borrows its stream, a FramedStream can be stored next to other connection state
+/// or moved into a spawned task.
+/// * Frames are read and written in the same [`FrameFormat`](crate::FrameFormat).
+/// # Example
+/// ```
+/// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+/// use send_it::async_framed::FramedStream;
+/// use send_it::Segment;
+///
+/// let (client, server) = tokio::io::duplex(64);
+/// let mut client = FramedStream::new(client);
+/// let mut server = FramedStream::new(server);
+///
+/// client.send(&[Segment::from("Hello, "), Segment::from("World!")]).await.unwrap();
+/// let data = server.read_data().await.unwrap().unwrap();
+/// assert_eq!(data[1].to_string(), "World!");
+/// # });
+/// ```
+pub struct FramedStream<S> {
+    core: ReaderCore<S>,
+}
+
+crate::framed::framed_stream_accessors!();
+
+impl<S: AsyncRead + Unpin> FramedStream<S> {
+    /// Reads a frame from the stream.
+    /// * Returns `Ok(None)` if the stream ended cleanly between two frames.
+    /// * Returns [`Error::TruncatedFrame`](crate::Error::TruncatedFrame) if the stream ended in the middle of a frame.
+    /// * Cancel safe: a partially read frame is kept if the future is dropped, so it can be used in `tokio::select!`.
+    pub async fn read_data(&mut self) -> Result<Option<Vec<Segment>>> {
+        poll_fn(|cx| self.core.poll_read_data(cx, poll_read)).await
+    }
+}
+
+impl<S: AsyncWrite + Unpin> FramedStream<S> {
+    /// Encodes the segments as one frame and sends it over the stream
+    pub async fn send(&mut self, segments: &[Segment]) -> Result<()> {
+        send_frame(self.core.decoder.format(), segments, &mut self.core.reader, poll_write_vectored).await
+    }
+}
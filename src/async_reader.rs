@@ -1,92 +1,85 @@
-use tokio::io::AsyncReadExt;
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
 
-use crate::Segment;
+use futures_core::stream::{FusedStream, Stream};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf};
 
-/// A reader that reads variable-length encoded data from a stream.
-/// Data is read in little-endian unless the big-endian feature is enabled.
-/// # Example
-/// ```
-/// use send_it::reader::VarReader;
-///
-/// // Create a sample stream, this is the output from the above test_writer test
-/// let stream: Vec<u8> = vec![21, 7, 0, 0, 0, 72, 101, 108, 108, 111, 44, 32, 6, 0, 0, 0, 87, 111, 114, 108, 100, 33];
-/// // turn the vector into a slice as Vec does not implement Read
-/// let mut fake_stream = stream.as_slice();
-///
-/// // create a new VarReader
-/// let mut reader = crate::reader::VarReader::new(&mut fake_stream);
-///
-/// let data = reader.read_data().unwrap();
-/// assert_eq!(data[0].to_string(), "Hello");
-/// assert_eq!(data[1].to_string(), "World");
-/// ```
-pub struct VarReader<'a, R: AsyncReadExt> {
-    reader: &'a mut R,
+use crate::poll_io::tokio_io::poll_read;
+use crate::poll_reader::ReaderCore;
+use crate::{Result, Segment};
+
+crate::poll_reader::var_reader! {
+    runtime: "tokio",
+    module: "async_reader",
+    block_on: "tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {",
+    read_ext: "tokio::io::AsyncReadExt",
+    select: "tokio::select!",
 }
 
-impl<'a, R: AsyncReadExt + Unpin> VarReader<'a, R> {
-    /// Create a new VarReader
-    pub fn new(reader: &'a mut R) -> Self {
-        VarReader { reader }
+impl<'a, R: AsyncRead + Unpin> VarReader<'a, R> {
+    /// Turns the reader into a [`Stream`] of frames, which ends when the stream ends cleanly between two frames.
+    /// # Example
+    /// ```
+    /// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+    /// use futures::StreamExt;
+    /// use send_it::async_reader::VarReader;
+    ///
+    /// let stream: Vec<u8> = vec![10, 6, 0, 0, 0, 87, 111, 114, 108, 100, 33, 10, 6, 0, 0, 0, 87, 111, 114, 108, 100, 33];
+    /// let mut fake_stream = stream.as_slice();
+    ///
+    /// let frames: Vec<_> = VarReader::new(&mut fake_stream).into_stream().collect().await;
+    /// assert_eq!(frames.len(), 2);
+    /// # });
+    /// ```
+    pub fn into_stream(self) -> FrameStream<&'a mut R> {
+        FrameStream { core: self.core, done: false }
     }
+}
 
-    async fn read_varint(&mut self) -> tokio::io::Result<usize> {
-        let mut value = 0usize;
-        let mut shift = 0;
-        loop {
-            let mut buf = [0; 1];
-            self.reader.read(&mut buf).await?;
-            let byte = buf[0];
-            value |= ((byte & 0x7F) as usize) << shift;
-            shift += 7;
-            if byte & 0x80 == 0 {
-                break;
-            }
-        }
-        Ok(value)
+impl<R: AsyncRead + Unpin> AsyncRead for SegmentReader<'_, R> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let read = ready!(self.get_mut().core.poll_read_segment(cx, poll_read, buf.initialize_unfilled()))?;
+        buf.advance(read);
+        Poll::Ready(Ok(()))
     }
+}
+
+/// A [`Stream`] of the frames read from a tokio stream, created by [`VarReader::into_stream`].
+/// * Yields an error if a frame is invalid or the stream ends in the middle of one, and ends after it.
+pub struct FrameStream<R> {
+    core: ReaderCore<R>,
+    done: bool,
+}
 
-    #[cfg(not(feature = "big-endian"))]
-    async fn read_u32(&mut self) -> tokio::io::Result<u32> {
-        let mut bytes = [0; 4];
-        self.reader.read_exact(&mut bytes).await?;
-        Ok(u32::from_le_bytes(bytes))
+impl<R> FrameStream<R> {
+    /// Returns the bytes read from the stream that have not been decoded yet
+    pub fn buffer(&self) -> &[u8] {
+        self.core.buffer()
     }
 
-    #[cfg(feature = "big-endian")]
-    async fn read_u32(&mut self) -> tokio::io::Result<u32> {
-        let mut bytes = [0; 4];
-        self.reader.read_exact(&mut bytes).await?;
-        Ok(u32::from_be_bytes(bytes))
+    /// Unwraps the FrameStream, returning the stream and the bytes read from it that have not been decoded yet
+    pub fn into_parts(self) -> (R, Vec<u8>) {
+        self.core.into_parts()
     }
+}
 
-    /// Reads data from the stream.
-    /// # Example
-    /// ```
-    /// use send_it::reader::VarReader;
-    ///
-    /// let stream: Vec<u8> = vec![21, 7, 0, 0, 0, 72, 101, 108, 108, 111, 44, 32, 6, 0, 0, 0, 87, 111, 114, 108, 100, 33];
-    /// // turn the vector into a slice as Vec does not implement Read
-    /// let mut fake_stream = stream.as_slice();
-    ///
-    /// // create a new VarReader
-    /// let mut reader = crate::reader::VarReader::new(&mut fake_stream);
-    ///
-    /// let data = reader.read_data().unwrap();
-    /// assert_eq!(data[0].to_string(), "Hello");
-    /// assert_eq!(data[1].to_string(), "World");
-    /// ```
-    pub async fn read_data(&mut self) -> tokio::io::Result<Vec<Segment>> {
-        let total_size = self.read_varint().await?;
-        let mut data = Vec::new();
+impl<R: AsyncRead + Unpin> Stream for FrameStream<R> {
+    type Item = Result<Vec<Segment>>;
 
-        while data.iter().map(|segment: &Segment| segment.len() + 4).sum::<usize>() < total_size {
-            let segment_size = self.read_u32().await? as usize;
-            let mut segment_data = vec![0u8; segment_size];
-            self.reader.read_exact(&mut segment_data).await?;
-            data.push(Segment::from(segment_data));
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.done {
+            return Poll::Ready(None);
         }
+        let frame = ready!(self.core.poll_read_data(cx, poll_read)).transpose();
+        // the stream can't be resynchronised after an error
+        self.done = !matches!(frame, Some(Ok(_)));
+        Poll::Ready(frame)
+    }
+}
 
-        Ok(data)
+impl<R: AsyncRead + Unpin> FusedStream for FrameStream<R> {
+    fn is_terminated(&self) -> bool {
+        self.done
     }
-}
\ No newline at end of file
+}
@@ -1,164 +1,156 @@
-use tokio::io::AsyncWriteExt;
-use crate::Segment;
-
+use std::io::ErrorKind;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use futures_sink::Sink;
+use tokio::io::{AsyncRead, AsyncWrite};
+use crate::encode::FrameHeader;
+use crate::poll_io::tokio_io::{poll_read, poll_write_vectored};
+use crate::{Error, FrameFormat, Result, Segment};
+
+crate::poll_writer::var_writer! {
+    runtime: "tokio",
+    module: "async_writer",
+    block_on: "tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {",
+    cursor: "std::io::Cursor",
+}
 
-/// A writer for sending several segments over a stream using variable length encoding
-/// Data is written in little-endian if the feature "big-endian" is not enabled
+/// A [`Sink`] of frames that owns the tokio stream it writes to.
+/// * Only one frame is buffered at a time: `poll_ready` waits until the previous frame has been written,
+///   so a slow peer holds back whatever is feeding the sink.
 /// # Example
 /// ```
-/// use send_it::writer::VarWriter;
-///
-/// let mut sender = VarWriter::new();
+/// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+/// use futures::SinkExt;
+/// use send_it::async_writer::FrameSink;
+/// use send_it::Segment;
 ///
-/// sender.add_string("Hello");
-/// sender.add_string("World");
+/// let mut sink = FrameSink::new(Vec::new());
+/// sink.send(vec![Segment::from("Hello, "), Segment::from("World!")]).await.unwrap();
 ///
-/// let mut buffer = Vec::new();
-/// sender.send(&mut buffer).unwrap();
+/// let buffer = sink.into_inner();
+/// assert_eq!(buffer.len(), 22);
+/// # });
 /// ```
-pub struct VarWriter {
-    data: Vec<Segment>,
+pub struct FrameSink<W> {
+    stream: W,
+    format: FrameFormat,
+    pending: Option<PendingFrame>,
 }
 
-impl VarWriter {
-    /// Create a new VarWriter
-    pub fn new() -> VarWriter {
-        VarWriter {
-            data: Vec::new(),
-        }
-    }
+/// A frame accepted by the sink that has not been fully written yet
+struct PendingFrame {
+    header: FrameHeader,
+    segments: Vec<Segment>,
+    /// The index of the first slice of the frame that has not been fully written
+    slice: usize,
+    /// The number of bytes of that slice that have been written
+    offset: usize,
+}
 
-    /// Add a segment to the writer
-    pub fn add(&mut self, segment: Segment) {
-        self.data.push(segment);
+impl<W> FrameSink<W> {
+    /// Create a new FrameSink writing frames in the default [`FrameFormat`]
+    pub fn new(stream: W) -> Self {
+        Self::with_format(stream, FrameFormat::default())
     }
 
-    /// Add a string to the writer
-    /// # Example
-    /// ```
-    /// use send_it::writer::VarWriter;
-    ///
-    /// let mut sender = VarWriter::new();
-    ///
-    /// sender.add_string("Hello");
-    /// ```
-    pub fn add_string<S: Into<String>>(&mut self, string: S) {
-        self.add(Segment::from(string.into()))
+    /// Create a new FrameSink that writes frames in the given format
+    pub fn with_format(stream: W, format: FrameFormat) -> Self {
+        FrameSink { stream, format, pending: None }
     }
 
-    /// Add raw data to the writer
-    /// # Example
-    /// ```
-    /// use send_it::writer::VarWriter;
-    ///
-    /// let mut sender = VarWriter::new();
-    ///
-    /// sender.add_raw(&[0x48, 0x65, 0x6C, 0x6C, 0x6F]);
-    /// ```
-    pub fn add_raw(&mut self, raw: &[u8]) {
-        self.data.push(Segment::from(raw));
+    /// Returns the format frames are written in
+    pub fn format(&self) -> &FrameFormat {
+        &self.format
     }
 
-    /// Encodes the data and sends it over the stream.
-    /// * The data is cleared after sending.
-    /// # Example
-    /// ```
-    /// use send_it::writer::VarWriter;
-    ///
-    /// let mut sender = VarWriter::new();
-    ///
-    /// sender.add_string("Hello");
-    /// sender.add_string("World");
-    ///
-    /// let mut buffer = Vec::new();
-    /// sender.send(&mut buffer).unwrap();
-    /// ```
-    pub async fn send<W: AsyncWriteExt + Unpin>(&mut self, stream: &mut W) -> std::io::Result<()> {
-        self.send_without_clearing(stream).await?;
-
-        // Clear the internal data after sending
-        self.clear();
+    /// Sets the format frames are written in, which must match the peer's reader
+    pub fn set_format(&mut self, format: FrameFormat) {
+        self.format = format;
+    }
 
-        Ok(())
+    /// Returns a reference to the underlying stream
+    pub fn get_ref(&self) -> &W {
+        &self.stream
     }
 
-    /// Encodes the data and sends it over the stream.
-    /// * The data is not cleared after sending.
-    /// # Example
-    /// ```
-    /// use send_it::writer::VarWriter;
-    ///
-    /// let mut sender = VarWriter::new();
-    ///
-    /// sender.add_string("Hello");
-    /// sender.add_string("World");
-    ///
-    /// let mut buffer = Vec::new();
-    /// sender.send_without_clearing(&mut buffer).unwrap();
-    /// ```
-    pub async fn send_without_clearing<W: AsyncWriteExt + Unpin>(&mut self, stream: &mut W) -> std::io::Result<()> {
-        let total_size: usize = self.data.iter().map(|segment| segment.len() + 4).sum();
-
-        // Write the total size as varint
-        self.write_varint(stream, total_size).await?;
-
-        // Write each segment's size and the segment itself
-        for segment in &self.data {
-            self.write_u32(stream, segment.len() as u32).await?;
-            // write the segment
-            stream.write_all(segment.as_ref()).await?;
-        }
+    /// Returns a mutable reference to the underlying stream
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.stream
+    }
 
-        Ok(())
+    /// Unwraps the FrameSink, returning the underlying stream.
+    /// * A frame that has not been flushed yet is lost.
+    pub fn into_inner(self) -> W {
+        self.stream
     }
+}
 
-    async fn write_varint<W: AsyncWriteExt + Unpin>(&self, writer: &mut W, mut value: usize) -> std::io::Result<()> {
-        loop {
-            let mut byte = (value & 0x7F) as u8;
-            value >>= 7;
-            if value != 0 {
-                byte |= 0x80;
-            }
-            writer.write_all(&[byte]).await?;
-            if value == 0 {
-                break;
+impl<W: AsyncWrite + Unpin> FrameSink<W> {
+    /// Writes the pending frame, if any, issuing as few writes as the stream allows
+    fn poll_write_pending(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let Some(frame) = &mut self.pending else {
+            return Poll::Ready(Ok(()));
+        };
+        let mut slices = frame.header.io_slices(&frame.segments);
+        if let Some(current) = slices.get_mut(frame.slice) {
+            current.advance(frame.offset);
+        }
+        while frame.slice < slices.len() {
+            let mut written = match ready!(Pin::new(&mut self.stream).poll_write_vectored(cx, &slices[frame.slice..])) {
+                Ok(0) => return Poll::Ready(Err(std::io::Error::from(ErrorKind::WriteZero).into())),
+                Ok(written) => written,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return Poll::Ready(Err(err.into())),
+            };
+            // move past the slices the write finished, into the one it stopped in
+            while written > 0 {
+                let current = &mut slices[frame.slice];
+                if written < current.len() {
+                    current.advance(written);
+                    frame.offset += written;
+                    break;
+                }
+                written -= current.len();
+                frame.slice += 1;
+                frame.offset = 0;
             }
         }
-        Ok(())
+        self.pending = None;
+        Poll::Ready(Ok(()))
     }
+}
 
-    #[cfg(not(feature = "big-endian"))]
-    async fn write_u32<W: AsyncWriteExt + Unpin>(&self, writer: &mut W, value: u32) -> std::io::Result<()> {
-        // writes little-endian
-        writer.write_all(&[
-            (value & 0xFF) as u8,
-            ((value >> 8) & 0xFF) as u8,
-            ((value >> 16) & 0xFF) as u8,
-            ((value >> 24) & 0xFF) as u8,
-        ]).await?;
-        Ok(())
+impl<W: AsyncWrite + Unpin> Sink<Vec<Segment>> for FrameSink<W> {
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.get_mut().poll_write_pending(cx)
     }
 
-    #[cfg(feature = "big-endian")]
-    async fn write_u32<W: AsyncWriteExt + Unpin>(&self, writer: &mut W, value: u32) -> std::io::Result<()> {
-        // writes big-endian
-        writer.write_all(&[
-            ((value >> 24) & 0xFF) as u8,
-            ((value >> 16) & 0xFF) as u8,
-            ((value >> 8) & 0xFF) as u8,
-            (value & 0xFF) as u8,
-        ]).await?;
+    /// Accepts a frame to be written.
+    /// * Fails with [`Error::LengthOverflow`] if a segment is too long for the format's segment length prefix.
+    /// * Fails without touching the previous frame if `poll_ready` has not finished writing it yet,
+    ///   since replacing a partly written frame would leave the stream out of step.
+    fn start_send(self: Pin<&mut Self>, item: Vec<Segment>) -> Result<()> {
+        let this = self.get_mut();
+        if this.pending.is_some() {
+            return Err(std::io::Error::other("start_send called before poll_ready finished the previous frame").into());
+        }
+        let header = FrameHeader::encode(&this.format, item.iter().map(Segment::len))?;
+        this.pending = Some(PendingFrame { header, segments: item, slice: 0, offset: 0 });
         Ok(())
     }
 
-    /// Removes all segments from the writer
-    pub fn clear(&mut self) {
-        self.data.clear();
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_pending(cx))?;
+        Poll::Ready(ready!(Pin::new(&mut this.stream).poll_flush(cx)).map_err(Error::from))
     }
-}
 
-impl Default for VarWriter {
-    fn default() -> Self {
-        Self::new()
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_pending(cx))?;
+        Poll::Ready(ready!(Pin::new(&mut this.stream).poll_shutdown(cx)).map_err(Error::from))
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,119 @@
+use tokio_util::bytes::{Buf, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::decoder::FrameDecoder;
+use crate::encode::FrameHeader;
+use crate::{ByteOrder, Error, FrameFormat, ReaderConfig, Result, Segment};
+
+/// A [`tokio_util::codec`] codec for send-it frames, to use with `Framed`, `FramedRead` and `FramedWrite`.
+/// Incoming frames are checked against the codec's [`ReaderConfig`] limits, and outgoing frames are checked against
+/// the same limits so that a frame the peer would reject is never sent.
+/// * Frames are decoded and encoded in the same [`FrameFormat`].
+/// # Example
+/// ```
+/// use send_it::codec::SendItCodec;
+/// use send_it::Segment;
+/// use tokio_util::bytes::BytesMut;
+/// use tokio_util::codec::{Decoder, Encoder};
+///
+/// let mut codec = SendItCodec::new();
+/// let mut buffer = BytesMut::new();
+/// codec.encode(vec![Segment::from("Hello, "), Segment::from("World!")], &mut buffer).unwrap();
+///
+/// let data = codec.decode(&mut buffer).unwrap().unwrap();
+/// assert_eq!(data[1].to_string(), "World!");
+/// ```
+#[derive(Default)]
+pub struct SendItCodec {
+    decoder: FrameDecoder,
+}
+
+impl SendItCodec {
+    /// Create a new SendItCodec with the default [`ReaderConfig`] limits
+    pub fn new() -> Self {
+        Self::with_config(ReaderConfig::default())
+    }
+
+    /// Create a new SendItCodec that enforces the given limits on frames
+    pub fn with_config(config: ReaderConfig) -> Self {
+        SendItCodec { decoder: FrameDecoder::with_config(config) }
+    }
+
+    /// Returns the limits this codec enforces
+    pub fn config(&self) -> &ReaderConfig {
+        self.decoder.config()
+    }
+
+    /// Replaces the limits this codec enforces
+    pub fn set_config(&mut self, config: ReaderConfig) {
+        self.decoder.set_config(config);
+    }
+
+    /// Returns the format frames are decoded and encoded in
+    pub fn format(&self) -> &FrameFormat {
+        self.decoder.format()
+    }
+
+    /// Sets the format frames are decoded and encoded in, which must match the peer's
+    pub fn set_format(&mut self, format: FrameFormat) {
+        self.decoder.set_format(format);
+    }
+
+    /// Returns the byte order fixed-width lengths are decoded and encoded in
+    pub fn byte_order(&self) -> ByteOrder {
+        self.decoder.byte_order()
+    }
+
+    /// Sets the byte order fixed-width lengths are decoded and encoded in, which must match the peer's
+    pub fn set_byte_order(&mut self, byte_order: ByteOrder) {
+        self.decoder.set_byte_order(byte_order);
+    }
+}
+
+impl Decoder for SendItCodec {
+    type Item = Vec<Segment>;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Vec<Segment>>> {
+        // partial frames are kept in the decoder, so everything it takes in can be dropped from `src`
+        let mut input = &src[..];
+        let frame = self.decoder.decode_from(&mut input);
+        let consumed = src.len() - input.len();
+        src.advance(consumed);
+        frame
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Vec<Segment>>> {
+        match self.decode(src)? {
+            Some(frame) => Ok(Some(frame)),
+            None => {
+                self.decoder.finish()?;
+                Ok(None)
+            }
+        }
+    }
+}
+
+impl Encoder<Vec<Segment>> for SendItCodec {
+    type Error = Error;
+
+    fn encode(&mut self, item: Vec<Segment>, dst: &mut BytesMut) -> Result<()> {
+        let config = self.decoder.config();
+        let format = self.decoder.format();
+        config.check_segment_count(item.len())?;
+        let mut total_size = 0usize;
+        for segment in &item {
+            config.check_segment_size(segment.len())?;
+            total_size = total_size.saturating_add(format.segment_size(segment.len())?);
+        }
+        config.check_frame_size(total_size)?;
+
+        let header = FrameHeader::encode(format, item.iter().map(Segment::len))?;
+        let slices = header.io_slices(&item);
+        dst.reserve(slices.iter().map(|slice| slice.len()).sum());
+        for slice in slices {
+            dst.extend_from_slice(&slice);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+/// The default maximum size of a single frame (64 MiB)
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 64 * 1024 * 1024;
+/// The default maximum size of a single segment (64 MiB)
+pub const DEFAULT_MAX_SEGMENT_SIZE: usize = 64 * 1024 * 1024;
+/// The default maximum number of segments in a single frame
+pub const DEFAULT_MAX_SEGMENT_COUNT: usize = 65_536;
+/// The default maximum size of a single segment read one at a time (4 GiB)
+pub const DEFAULT_MAX_STREAMED_SEGMENT_SIZE: usize = u32::MAX as usize;
+
+/// Limits a VarReader enforces on the frames it reads from a peer.
+/// A peer exceeding any of these limits causes `read_data` to fail with an [`Error`](crate::Error)
+/// before the offending data is allocated.
+/// # Example
+/// ```
+/// use send_it::ReaderConfig;
+///
+/// let config = ReaderConfig {
+///     max_frame_size: 1024,
+///     ..ReaderConfig::default()
+/// };
+///
+/// assert_eq!(config.max_frame_size, 1024);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReaderConfig {
+    /// The maximum total size of a frame, as declared by its header
+    pub max_frame_size: usize,
+    /// The maximum size of a single segment
+    pub max_segment_size: usize,
+    /// The maximum number of segments in a single frame
+    pub max_segment_count: usize,
+    /// The maximum size of a single segment read one at a time, such as with `next_frame` or `read_data_with`.
+    /// * These segments are never held in memory as a whole, so frames read this way are checked against this
+    ///   limit instead of `max_frame_size` and `max_segment_size`.
+    pub max_streamed_segment_size: usize,
+    /// How strictly frame headers are validated
+    pub mode: DecodeMode,
+}
+
+/// How strictly a VarReader validates the headers of incoming frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DecodeMode {
+    /// Rejects frames whose segments overshoot the declared total size
+    /// and over-long (non-canonical) varints.
+    #[default]
+    Strict,
+    /// Accepts frames whose segments overshoot the declared total size and over-long varints,
+    /// for compatibility with peers that produce them.
+    Lenient,
+}
+
+impl ReaderConfig {
+    /// Creates a new ReaderConfig with the default limits
+    pub fn new() -> Self {
+        Self {
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            max_segment_size: DEFAULT_MAX_SEGMENT_SIZE,
+            max_segment_count: DEFAULT_MAX_SEGMENT_COUNT,
+            max_streamed_segment_size: DEFAULT_MAX_STREAMED_SEGMENT_SIZE,
+            mode: DecodeMode::Strict,
+        }
+    }
+
+    /// Creates a ReaderConfig without any limits.
+    /// * Only use this with trusted peers.
+    pub fn unlimited() -> Self {
+        Self {
+            max_frame_size: usize::MAX,
+            max_segment_size: usize::MAX,
+            max_segment_count: usize::MAX,
+            max_streamed_segment_size: usize::MAX,
+            mode: DecodeMode::Strict,
+        }
+    }
+
+    #[cfg(feature="reading")]
+    pub(crate) fn check_frame_size(&self, size: usize) -> crate::Result<()> {
+        if size > self.max_frame_size {
+            return Err(crate::Error::FrameTooLarge { size, max: self.max_frame_size });
+        }
+        Ok(())
+    }
+
+    #[cfg(feature="reading")]
+    pub(crate) fn check_segment_size(&self, size: usize) -> crate::Result<()> {
+        if size > self.max_segment_size {
+            return Err(crate::Error::SegmentTooLarge { size, max: self.max_segment_size });
+        }
+        Ok(())
+    }
+
+    #[cfg(feature="reading")]
+    pub(crate) fn check_streamed_segment_size(&self, size: usize) -> crate::Result<()> {
+        if size > self.max_streamed_segment_size {
+            return Err(crate::Error::SegmentTooLarge { size, max: self.max_streamed_segment_size });
+        }
+        Ok(())
+    }
+
+    #[cfg(feature="reading")]
+    pub(crate) fn check_segment_count(&self, count: usize) -> crate::Result<()> {
+        if count > self.max_segment_count {
+            return Err(crate::Error::TooManySegments { max: self.max_segment_count });
+        }
+        Ok(())
+    }
+
+    /// Checks that a segment header and its payload fit within the declared frame size
+    #[cfg(feature="reading")]
+    pub(crate) fn check_segment_fits(&self, total_size: usize, segment_end: usize) -> crate::Result<()> {
+        if self.mode == DecodeMode::Strict && segment_end > total_size {
+            return Err(crate::Error::InconsistentSize { declared: total_size, actual: segment_end });
+        }
+        Ok(())
+    }
+}
+
+impl Default for ReaderConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
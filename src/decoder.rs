@@ -0,0 +1,471 @@
+use std::mem;
+
+use crate::format::{to_usize, VarintDecoder};
+use crate::{ByteOrder, DecodeMode, Error, FrameFormat, LengthPrefix, ReaderConfig, Result, Segment};
+
+/// The capacity allocated for a segment before any of its data has been decoded
+const INITIAL_SEGMENT_CAPACITY: usize = 8 * 1024;
+
+/// A push-based decoder that turns arbitrary chunks of bytes into frames, without doing any IO itself.
+/// Bytes are handed to the decoder with [`FrameDecoder::feed`] as they arrive, and complete frames are taken out
+/// with [`FrameDecoder::decode`]. Partially received frames are kept between calls.
+///
+/// This makes it usable from event loops that can't block on a stream, such as mio or a custom epoll loop.
+/// * After `decode` returns an error the stream can't be resynchronised and the decoder should be dropped.
+/// # Example
+/// ```
+/// use send_it::decoder::FrameDecoder;
+///
+/// let stream: Vec<u8> = vec![21, 7, 0, 0, 0, 72, 101, 108, 108, 111, 44, 32, 6, 0, 0, 0, 87, 111, 114, 108, 100, 33];
+///
+/// let mut decoder = FrameDecoder::new();
+///
+/// // the frame arrives in two chunks
+/// decoder.feed(&stream[..10]);
+/// assert!(decoder.decode().unwrap().is_none());
+/// decoder.feed(&stream[10..]);
+///
+/// let data = decoder.decode().unwrap().unwrap();
+/// assert_eq!(data[0].to_string(), "Hello, ");
+/// assert_eq!(data[1].to_string(), "World!");
+///
+/// // the stream ended at a frame boundary
+/// decoder.finish().unwrap();
+/// ```
+pub struct FrameDecoder {
+    config: ReaderConfig,
+    format: FrameFormat,
+    buf: Vec<u8>,
+    pos: usize,
+    filled: usize,
+    state: State,
+    segments: Vec<Segment>,
+    segment_count: usize,
+    /// Whether the current frame is read one segment at a time and never held in memory
+    streamed: bool,
+}
+
+/// What the decoder expects next
+enum State {
+    /// The length prefix of a frame
+    FrameLength(LengthDecoder),
+    /// The length prefix of a segment
+    SegmentLength { total_size: usize, read_size: usize, length: LengthDecoder },
+    /// The data of a segment
+    SegmentData { total_size: usize, segment_end: usize, size: usize, data: Vec<u8> },
+    /// The data of a segment that is handed out as it arrives instead of being collected
+    SegmentStream { total_size: usize, segment_end: usize, remaining: usize },
+}
+
+/// What the decoder found when starting the next segment of a lazily read frame
+pub(crate) enum SegmentStart {
+    /// A segment of the given size
+    Segment(usize),
+    /// The end of the frame
+    FrameEnd,
+}
+
+impl FrameDecoder {
+    /// Create a new FrameDecoder with the default [`ReaderConfig`] limits
+    pub fn new() -> Self {
+        Self::with_config(ReaderConfig::default())
+    }
+
+    /// Create a new FrameDecoder that enforces the given limits on incoming frames
+    pub fn with_config(config: ReaderConfig) -> Self {
+        FrameDecoder {
+            config,
+            format: FrameFormat::default(),
+            buf: Vec::new(),
+            pos: 0,
+            filled: 0,
+            state: State::FrameLength(LengthDecoder::default()),
+            segments: Vec::new(),
+            segment_count: 0,
+            streamed: false,
+        }
+    }
+
+    /// Returns the limits this decoder enforces
+    pub fn config(&self) -> &ReaderConfig {
+        &self.config
+    }
+
+    /// Replaces the limits this decoder enforces
+    pub fn set_config(&mut self, config: ReaderConfig) {
+        self.config = config;
+    }
+
+    /// Returns the format frames are decoded in
+    pub fn format(&self) -> &FrameFormat {
+        &self.format
+    }
+
+    /// Sets the format frames are decoded in, which must match the peer's writer
+    pub fn set_format(&mut self, format: FrameFormat) {
+        self.format = format;
+    }
+
+    /// Returns the byte order fixed-width lengths are decoded in
+    pub fn byte_order(&self) -> ByteOrder {
+        self.format.byte_order
+    }
+
+    /// Sets the byte order fixed-width lengths are decoded in, which must match the peer's writer
+    pub fn set_byte_order(&mut self, byte_order: ByteOrder) {
+        self.format.byte_order = byte_order;
+    }
+
+    /// Adds bytes received from the stream to the decoder
+    pub fn feed(&mut self, data: &[u8]) {
+        self.read_buf(data.len())[..data.len()].copy_from_slice(data);
+        self.filled += data.len();
+    }
+
+    /// Returns the bytes fed to the decoder that have not been decoded yet
+    pub fn buffer(&self) -> &[u8] {
+        &self.buf[self.pos..self.filled]
+    }
+
+    /// Returns true if the decoder holds part of a frame
+    pub fn is_mid_frame(&self) -> bool {
+        match &self.state {
+            State::FrameLength(length) => length.len > 0 || self.pos < self.filled,
+            _ => true,
+        }
+    }
+
+    /// Signals the end of the stream.
+    /// * Returns [`Error::TruncatedFrame`] if the stream ended in the middle of a frame.
+    pub fn finish(&self) -> Result<()> {
+        if self.is_mid_frame() {
+            return Err(Error::TruncatedFrame);
+        }
+        Ok(())
+    }
+
+    /// Decodes the next frame from the bytes fed so far.
+    /// * Returns `Ok(None)` if more bytes are needed to complete the frame, after taking in all the bytes fed so far.
+    /// * Only one frame is decoded per call, call it again until it returns `Ok(None)` to get all complete frames.
+    pub fn decode(&mut self) -> Result<Option<Vec<Segment>>> {
+        self.with_buffer(Self::decode_from)
+    }
+
+    /// Runs `decode` on the bytes fed so far, dropping the ones it took in from the buffer
+    fn with_buffer<T>(&mut self, decode: impl FnOnce(&mut Self, &mut &[u8]) -> T) -> T {
+        let buf = mem::take(&mut self.buf);
+        let mut input = &buf[self.pos..self.filled];
+        let result = decode(self, &mut input);
+        self.pos = self.filled - input.len();
+        if self.pos == self.filled {
+            self.pos = 0;
+            self.filled = 0;
+        }
+        self.buf = buf;
+        result
+    }
+
+    /// Decodes the next frame from `input` rather than the bytes fed so far, advancing it past the bytes taken in.
+    /// * Used where the caller owns the buffer, so that bytes are copied only once.
+    pub(crate) fn decode_from(&mut self, input: &mut &[u8]) -> Result<Option<Vec<Segment>>> {
+        loop {
+            match self.state {
+                State::FrameLength(_) => {
+                    self.decode_frame_length(input, false)?;
+                }
+                State::SegmentLength { total_size, read_size, .. } if read_size >= total_size => {
+                    // every segment of the frame has been decoded
+                    self.state = State::FrameLength(LengthDecoder::default());
+                    return Ok(Some(mem::take(&mut self.segments)));
+                }
+                State::SegmentLength { .. } => {
+                    self.decode_segment_length(input, false)?;
+                }
+                State::SegmentData { total_size, segment_end, size, ref mut data } => {
+                    let read = (size - data.len()).min(input.len());
+                    data.extend_from_slice(&input[..read]);
+                    *input = &input[read..];
+                    if data.len() == size {
+                        self.segments.push(Segment::from(mem::take(data)));
+                        self.state = State::SegmentLength { total_size, read_size: segment_end, length: LengthDecoder::default() };
+                    }
+                }
+                State::SegmentStream { remaining, .. } => {
+                    // the rest of a segment that was being read lazily is of no use to a whole frame
+                    let read = remaining.min(input.len());
+                    *input = &input[read..];
+                    self.consume_segment_data(read);
+                }
+            }
+
+            // stop once the input is exhausted and the state can't advance without more of it
+            if input.is_empty() && !self.can_advance() {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Decodes the length prefix of a frame, returning true once it is complete.
+    /// * A `streamed` frame is bound by the size of its segments rather than its own, as it is never held in memory.
+    fn decode_frame_length(&mut self, input: &mut &[u8], streamed: bool) -> Result<bool> {
+        let State::FrameLength(length) = &mut self.state else {
+            return Ok(true);
+        };
+        let Some(total_size) = length.decode(self.format.frame_length, &self.format, self.config.mode, input)? else {
+            return Ok(false);
+        };
+        let total_size = to_usize(total_size);
+        if !streamed {
+            self.config.check_frame_size(total_size)?;
+        }
+        self.streamed = streamed;
+        self.segment_count = 0;
+        self.state = State::SegmentLength { total_size, read_size: 0, length: LengthDecoder::default() };
+        Ok(true)
+    }
+
+    /// Decodes the length prefix of a segment, returning its size once it is complete.
+    /// * A `lazy` segment's data is handed out with `take_segment_data` rather than collected.
+    fn decode_segment_length(&mut self, input: &mut &[u8], lazy: bool) -> Result<Option<usize>> {
+        let State::SegmentLength { total_size, read_size, length } = &mut self.state else {
+            return Ok(None);
+        };
+        if length.len == 0 {
+            self.config.check_segment_count(self.segment_count + 1)?;
+        }
+        let Some(size) = length.decode(self.format.segment_length, &self.format, self.config.mode, input)? else {
+            return Ok(None);
+        };
+        let size = to_usize(size);
+        if lazy && self.streamed {
+            self.config.check_streamed_segment_size(size)?;
+        } else {
+            self.config.check_segment_size(size)?;
+        }
+        let segment_end = read_size.saturating_add(length.len).saturating_add(size);
+        self.config.check_segment_fits(*total_size, segment_end)?;
+        self.segment_count += 1;
+        let total_size = *total_size;
+        self.state = if lazy {
+            State::SegmentStream { total_size, segment_end, remaining: size }
+        } else {
+            // grow the segment as data arrives rather than trusting the declared size up front
+            let data = Vec::with_capacity(size.min(INITIAL_SEGMENT_CAPACITY));
+            State::SegmentData { total_size, segment_end, size, data }
+        };
+        // a lazily read empty segment has no data to hand out
+        self.consume_segment_data(0);
+        Ok(Some(size))
+    }
+
+    /// Decodes the length of the next frame from the bytes fed so far, so that its segments can be read one at a time.
+    /// * Returns false if more bytes are needed.
+    /// * A `streamed` frame's segments are checked against `max_streamed_segment_size` rather than the frame and
+    ///   segment limits, for callers that never hold them in memory.
+    pub(crate) fn decode_frame_start(&mut self, streamed: bool) -> Result<bool> {
+        self.with_buffer(|decoder, input| decoder.decode_frame_length(input, streamed))
+    }
+
+    /// Decodes the length of the next segment of a frame started with `decode_frame_start`.
+    /// * Returns `Ok(None)` if more bytes are needed.
+    pub(crate) fn decode_segment_start(&mut self) -> Result<Option<SegmentStart>> {
+        if let State::SegmentLength { total_size, read_size, .. } = self.state {
+            if read_size >= total_size {
+                self.state = State::FrameLength(LengthDecoder::default());
+                return Ok(Some(SegmentStart::FrameEnd));
+            }
+        }
+        let size = self.with_buffer(|decoder, input| decoder.decode_segment_length(input, true))?;
+        Ok(size.map(SegmentStart::Segment))
+    }
+
+    /// Returns the number of bytes of the lazily read segment that have not been taken yet
+    pub(crate) fn segment_remaining(&self) -> usize {
+        match self.state {
+            State::SegmentStream { remaining, .. } => remaining,
+            _ => 0,
+        }
+    }
+
+    /// Takes up to `max` buffered bytes of the lazily read segment
+    pub(crate) fn take_segment_data(&mut self, max: usize) -> &[u8] {
+        let start = self.pos;
+        let read = (self.filled - self.pos).min(self.segment_remaining()).min(max);
+        self.pos += read;
+        if self.pos == self.filled {
+            self.pos = 0;
+            self.filled = 0;
+        }
+        self.consume_segment_data(read);
+        &self.buf[start..start + read]
+    }
+
+    /// Marks `read` bytes of the lazily read segment as taken, such as bytes read directly from the stream,
+    /// moving on to the next segment once all of them are
+    pub(crate) fn consume_segment_data(&mut self, read: usize) {
+        if let State::SegmentStream { total_size, segment_end, remaining } = &mut self.state {
+            *remaining -= read;
+            if *remaining == 0 {
+                self.state = State::SegmentLength { total_size: *total_size, read_size: *segment_end, length: LengthDecoder::default() };
+            }
+        }
+    }
+
+    /// Returns the least number of bytes, beyond those taken in so far, needed to complete the current frame
+    fn bytes_needed(&self) -> usize {
+        match &self.state {
+            State::FrameLength(length) => match self.format.frame_length.width() {
+                Some(width) => width - length.len,
+                None => 1,
+            },
+            // a frame whose segments have all been read needs nothing more
+            State::SegmentLength { total_size, read_size, .. } if read_size >= total_size => 0,
+            State::SegmentLength { total_size, read_size, length } => {
+                total_size.saturating_sub(read_size.saturating_add(length.len)).max(1)
+            }
+            State::SegmentData { total_size, segment_end, size, data } => {
+                (size - data.len()).saturating_add(total_size.saturating_sub(*segment_end))
+            }
+            State::SegmentStream { total_size, segment_end, remaining } => {
+                remaining.saturating_add(total_size.saturating_sub(*segment_end))
+            }
+        }
+    }
+
+    /// Returns true if the current state can make progress without more input
+    fn can_advance(&self) -> bool {
+        match &self.state {
+            State::FrameLength(_) => false,
+            State::SegmentLength { total_size, read_size, .. } => read_size >= total_size,
+            State::SegmentData { size, data, .. } => data.len() == *size,
+            State::SegmentStream { remaining, .. } => *remaining == 0,
+        }
+    }
+
+    /// Returns at least `min` bytes of spare space at the end of the buffer for reading into
+    pub(crate) fn read_buf(&mut self, min: usize) -> &mut [u8] {
+        if self.pos > 0 && self.buf.len() - self.filled < min {
+            self.buf.copy_within(self.pos..self.filled, 0);
+            self.filled -= self.pos;
+            self.pos = 0;
+        }
+        if self.buf.len() - self.filled < min {
+            self.buf.resize(self.filled + min, 0);
+        }
+        &mut self.buf[self.filled..]
+    }
+
+    /// Marks `read` bytes of the space returned by `read_buf` as filled
+    pub(crate) fn commit(&mut self, read: usize) {
+        self.filled += read;
+    }
+
+    /// Returns the segment currently being read and how many of its bytes are still missing,
+    /// so that large segments can be read directly into it
+    pub(crate) fn pending_segment(&mut self) -> Option<(&mut Vec<u8>, usize)> {
+        if self.pos < self.filled {
+            return None;
+        }
+        match &mut self.state {
+            State::SegmentData { size, data, .. } => {
+                let remaining = *size - data.len();
+                Some((data, remaining))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl FrameFormat {
+    /// Decodes one frame in this format from the start of `input`, without a stream, enforcing the given limits.
+    /// * Returns the frame's segments and the number of bytes of `input` the frame took up.
+    /// * Fails with [`Error::Incomplete`] if `input` holds only part of a frame, telling how many more bytes are needed.
+    /// # Example
+    /// ```
+    /// use send_it::{Error, FrameFormat, ReaderConfig};
+    ///
+    /// let frame: Vec<u8> = vec![21, 7, 0, 0, 0, 72, 101, 108, 108, 111, 44, 32, 6, 0, 0, 0, 87, 111, 114, 108, 100, 33];
+    ///
+    /// match FrameFormat::default().decode_frame(&frame[..10], ReaderConfig::default()) {
+    ///     Err(Error::Incomplete { needed }) => assert_eq!(needed, 12),
+    ///     other => panic!("unexpected result: {:?}", other),
+    /// }
+    /// ```
+    pub fn decode_frame(&self, input: &[u8], config: ReaderConfig) -> Result<(Vec<Segment>, usize)> {
+        let mut decoder = FrameDecoder::with_config(config);
+        decoder.set_format(*self);
+        let mut rest = input;
+        // check the whole frame is there before decoding any of its segments
+        if decoder.decode_frame_length(&mut rest, false)? && decoder.bytes_needed() > rest.len() {
+            return Err(Error::Incomplete { needed: decoder.bytes_needed() - rest.len() });
+        }
+        match decoder.decode_from(&mut rest)? {
+            Some(frame) => Ok((frame, input.len() - rest.len())),
+            None => Err(Error::Incomplete { needed: decoder.bytes_needed() }),
+        }
+    }
+}
+
+/// Decodes one frame in the default [`FrameFormat`] from the start of `input`, without a stream,
+/// enforcing the default [`ReaderConfig`] limits.
+/// * Returns the frame's segments and the number of bytes of `input` the frame took up, so that the next frame
+///   can be decoded from the rest.
+/// * Fails with [`Error::Incomplete`] if `input` holds only part of a frame, telling how many more bytes are needed.
+/// # Example
+/// ```
+/// use send_it::{decode_frame, Error};
+///
+/// let stream: Vec<u8> = vec![21, 7, 0, 0, 0, 72, 101, 108, 108, 111, 44, 32, 6, 0, 0, 0, 87, 111, 114, 108, 100, 33];
+///
+/// let (segments, consumed) = decode_frame(&stream).unwrap();
+/// assert_eq!(segments[0].to_string(), "Hello, ");
+/// assert_eq!(consumed, stream.len());
+///
+/// assert!(matches!(decode_frame(&stream[..1]), Err(Error::Incomplete { needed: 21 })));
+/// ```
+pub fn decode_frame(input: &[u8]) -> Result<(Vec<Segment>, usize)> {
+    FrameFormat::default().decode_frame(input, ReaderConfig::default())
+}
+
+impl Default for FrameDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The progress of a length prefix being decoded
+#[derive(Default)]
+struct LengthDecoder {
+    varint: VarintDecoder,
+    bytes: [u8; 8],
+    /// The number of bytes of the prefix consumed so far
+    len: usize,
+}
+
+impl LengthDecoder {
+    /// Consumes bytes from `input` until the length is complete, returning it once it is
+    fn decode(&mut self, prefix: LengthPrefix, format: &FrameFormat, mode: DecodeMode, input: &mut &[u8]) -> Result<Option<u64>> {
+        match prefix.width() {
+            Some(width) => {
+                let read = (width - self.len).min(input.len());
+                self.bytes[self.len..self.len + read].copy_from_slice(&input[..read]);
+                *input = &input[read..];
+                self.len += read;
+                if self.len < width {
+                    return Ok(None);
+                }
+                Ok(Some(format.decode_fixed(&self.bytes[..width])))
+            }
+            None => {
+                while let Some((&byte, rest)) = input.split_first() {
+                    *input = rest;
+                    self.len += 1;
+                    if let Some(value) = self.varint.push(byte, mode)? {
+                        return Ok(Some(value));
+                    }
+                }
+                Ok(None)
+            }
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use std::io::IoSlice;
+
+use crate::{Error, FrameFormat, Result, Segment};
+
+/// The length prefixes of a frame, encoded into a single buffer so that a frame can be sent
+/// as one vectored write of prefixes interleaved with the segments' data.
+pub(crate) struct FrameHeader {
+    bytes: Vec<u8>,
+    /// The end of the prefixes preceding each segment in `bytes`
+    ends: Vec<usize>,
+}
+
+impl FrameHeader {
+    /// Encodes the prefixes for segments of the given lengths.
+    /// * Every length is checked against the format before anything is encoded.
+    pub(crate) fn encode<I>(format: &FrameFormat, lengths: I) -> Result<Self>
+    where
+        I: Iterator<Item = usize> + Clone,
+    {
+        let total_size = Self::total_size(format, lengths.clone())?;
+        let mut bytes = Vec::new();
+        format.encode_frame_length(total_size, &mut bytes)?;
+        let mut ends = Vec::new();
+        for length in lengths {
+            format.encode_segment_length(length, &mut bytes)?;
+            ends.push(bytes.len());
+        }
+        Ok(Self { bytes, ends })
+    }
+
+    /// Returns the size of a frame holding segments of the given lengths, excluding its own length prefix
+    pub(crate) fn total_size<I: Iterator<Item = usize>>(format: &FrameFormat, lengths: I) -> Result<usize> {
+        let mut total_size = 0usize;
+        for length in lengths {
+            let segment_size = format.segment_size(length)?;
+            total_size = total_size.checked_add(segment_size)
+                .ok_or(Error::LengthOverflow { length: (total_size as u64).saturating_add(segment_size as u64), max: usize::MAX as u64 })?;
+        }
+        Ok(total_size)
+    }
+
+    /// Returns the prefixes preceding each segment, the first one including the frame length
+    pub(crate) fn prefixes(&self) -> impl Iterator<Item = &[u8]> {
+        let starts = std::iter::once(0).chain(self.ends.iter().copied());
+        starts.zip(&self.ends).map(|(start, end)| &self.bytes[start..*end])
+    }
+
+    /// Returns the encoded frame as slices of prefixes interleaved with the given segments' data
+    pub(crate) fn io_slices<'a, S: AsRef<[u8]>>(&'a self, segments: &'a [S]) -> Vec<IoSlice<'a>> {
+        if segments.is_empty() {
+            return vec![IoSlice::new(&self.bytes)];
+        }
+        let mut slices = Vec::with_capacity(segments.len() * 2);
+        for (prefix, segment) in self.prefixes().zip(segments) {
+            slices.push(IoSlice::new(prefix));
+            if !segment.as_ref().is_empty() {
+                slices.push(IoSlice::new(segment.as_ref()));
+            }
+        }
+        slices
+    }
+}
+
+impl FrameFormat {
+    /// Encodes the segments as one frame in this format, without a stream.
+    /// * Fails with [`Error::LengthOverflow`] if a segment is too long for the segment length prefix.
+    /// # Example
+    /// ```
+    /// use send_it::{FrameFormat, LengthPrefix, Segment};
+    ///
+    /// let format = FrameFormat { segment_length: LengthPrefix::U8, ..FrameFormat::default() };
+    /// let frame = format.encode_frame(&[Segment::from("Hello")]).unwrap();
+    /// assert_eq!(frame, [6, 5, 72, 101, 108, 108, 111]);
+    /// ```
+    pub fn encode_frame(&self, segments: &[Segment]) -> Result<Vec<u8>> {
+        let header = FrameHeader::encode(self, segments.iter().map(Segment::len))?;
+        let slices = header.io_slices(segments);
+        let mut frame = Vec::with_capacity(slices.iter().map(|slice| slice.len()).sum());
+        for slice in slices {
+            frame.extend_from_slice(&slice);
+        }
+        Ok(frame)
+    }
+
+    /// Returns the number of bytes the segments take up once encoded as one frame in this format.
+    /// * Fails with [`Error::LengthOverflow`] if a segment is too long for the segment length prefix.
+    pub fn encoded_len(&self, segments: &[Segment]) -> Result<usize> {
+        let total_size = FrameHeader::total_size(self, segments.iter().map(Segment::len))?;
+        self.frame_length.check(total_size as u64)?;
+        let prefix_len = self.frame_length.encoded_len(total_size as u64);
+        prefix_len.checked_add(total_size)
+            .ok_or(Error::LengthOverflow { length: (total_size as u64).saturating_add(prefix_len as u64), max: usize::MAX as u64 })
+    }
+}
+
+/// Encodes the segments as one frame in the default [`FrameFormat`], without a stream.
+/// * The result is the same bytes [`VarWriter::send`](crate::writer::VarWriter::send) writes.
+/// # Panics
+/// If a segment is longer than the 4 GiB the default `u32` segment lengths can encode,
+/// use [`FrameFormat::encode_frame`] with [`LengthPrefix::U64`](crate::LengthPrefix::U64) lengths instead.
+/// # Example
+/// ```
+/// use send_it::{decode_frame, encode_frame, encoded_len, Segment};
+///
+/// let segments = [Segment::from("Hello, "), Segment::from("World!")];
+/// let frame = encode_frame(&segments);
+/// assert_eq!(frame.len(), encoded_len(&segments));
+///
+/// let (decoded, consumed) = decode_frame(&frame).unwrap();
+/// assert_eq!(decoded[1].to_string(), "World!");
+/// assert_eq!(consumed, frame.len());
+/// ```
+pub fn encode_frame(segments: &[Segment]) -> Vec<u8> {
+    match FrameFormat::default().encode_frame(segments) {
+        Ok(frame) => frame,
+        Err(err) => panic!("failed to encode frame: {}", err),
+    }
+}
+
+/// Returns the number of bytes the segments take up once encoded as one frame in the default [`FrameFormat`].
+/// # Panics
+/// If a segment is longer than the 4 GiB the default `u32` segment lengths can encode.
+pub fn encoded_len(segments: &[Segment]) -> usize {
+    match FrameFormat::default().encoded_len(segments) {
+        Ok(len) => len,
+        Err(err) => panic!("failed to encode frame: {}", err),
+    }
+}
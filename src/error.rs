@@ -0,0 +1,111 @@
+use std::fmt::Display;
+
+/// A specialized `Result` type for send-it operations.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The errors that can occur while sending or reading data.
+/// # Example
+/// ```
+/// use send_it::reader::VarReader;
+/// use send_it::Error;
+///
+/// // a frame declaring a 7 byte segment, cut off after 3 bytes
+/// let stream: Vec<u8> = vec![11, 7, 0, 0, 0, 72, 101, 108];
+/// let mut fake_stream = stream.as_slice();
+///
+/// let mut reader = VarReader::new(&mut fake_stream);
+///
+/// match reader.read_data() {
+///     Err(Error::TruncatedFrame) => println!("the peer hung up mid-frame"),
+///     other => panic!("unexpected result: {:?}", other),
+/// }
+/// ```
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The underlying stream failed
+    Io(std::io::Error),
+    /// The stream ended in the middle of a frame
+    TruncatedFrame,
+    /// A variable-length integer did not fit in 64 bits
+    VarintOverflow,
+    /// A variable-length integer was encoded with more bytes than necessary
+    NonCanonicalVarint,
+    /// The frame header declared a size larger than the reader's `max_frame_size`
+    FrameTooLarge { size: usize, max: usize },
+    /// A segment was larger than the reader's `max_segment_size`
+    SegmentTooLarge { size: usize, max: usize },
+    /// The frame contained more than the reader's `max_segment_count` segments
+    TooManySegments { max: usize },
+    /// The segments of a frame did not add up to the size declared in its header
+    InconsistentSize { declared: usize, actual: usize },
+    /// A length was too large to be encoded with the frame format's length prefix
+    LengthOverflow { length: u64, max: u64 },
+    /// A segment's source ended before the length it was added with, leaving a partial frame on the stream
+    SourceTooShort { declared: usize, read: usize },
+    /// A buffer held only part of a frame, and at least `needed` more bytes are required to decode it
+    Incomplete { needed: usize },
+    /// A file range added to a frame ended before it started
+    InvalidRange { start: u64, end: u64 },
+}
+
+impl Error {
+    /// Returns true if the error was caused by the peer sending invalid or unacceptable data,
+    /// as opposed to the stream itself failing or ending early, or the data being sent not fitting the frame format.
+    pub fn is_protocol_error(&self) -> bool {
+        !matches!(
+            self,
+            Error::Io(_) | Error::TruncatedFrame | Error::SourceTooShort { .. } | Error::Incomplete { .. }
+                | Error::LengthOverflow { .. } | Error::InvalidRange { .. }
+        )
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "io error: {}", err),
+            Error::TruncatedFrame => write!(f, "stream ended in the middle of a frame"),
+            Error::VarintOverflow => write!(f, "varint does not fit in 64 bits"),
+            Error::NonCanonicalVarint => write!(f, "varint is encoded with more bytes than necessary"),
+            Error::FrameTooLarge { size, max } => write!(f, "frame size {} exceeds the limit of {} bytes", size, max),
+            Error::SegmentTooLarge { size, max } => write!(f, "segment size {} exceeds the limit of {} bytes", size, max),
+            Error::TooManySegments { max } => write!(f, "frame contains more than {} segments", max),
+            Error::InconsistentSize { declared, actual } => write!(f, "frame declared {} bytes but its segments span {} bytes", declared, actual),
+            Error::LengthOverflow { length, max } => write!(f, "length {} exceeds the maximum of {} its length prefix can encode", length, max),
+            Error::SourceTooShort { declared, read } => write!(f, "segment source ended after {} of its {} bytes", read, declared),
+            Error::Incomplete { needed } => write!(f, "incomplete frame, need at least {} more bytes", needed),
+            Error::InvalidRange { start, end } => write!(f, "range {}..{} ends before it starts", start, end),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self {
+        Error::Io(value)
+    }
+}
+
+impl From<Error> for std::io::Error {
+    fn from(value: Error) -> Self {
+        match value {
+            Error::Io(err) => err,
+            Error::TruncatedFrame | Error::SourceTooShort { .. } | Error::Incomplete { .. } => {
+                std::io::Error::new(std::io::ErrorKind::UnexpectedEof, value)
+            }
+            Error::LengthOverflow { .. } | Error::InvalidRange { .. } => {
+                std::io::Error::new(std::io::ErrorKind::InvalidInput, value)
+            }
+            _ => std::io::Error::new(std::io::ErrorKind::InvalidData, value),
+        }
+    }
+}
@@ -0,0 +1,249 @@
+#[cfg(any(feature="reading", feature="writing"))]
+use crate::{Error, Result};
+
+/// The byte order used for the fixed-width lengths of a frame.
+/// * Both peers must use the same byte order.
+/// # Example
+/// ```
+/// use send_it::ByteOrder;
+/// use send_it::writer::VarWriter;
+///
+/// let mut sender = VarWriter::with_byte_order(ByteOrder::BigEndian);
+/// sender.add_string("Hello");
+///
+/// let mut buffer = Vec::new();
+/// sender.send(&mut buffer).unwrap();
+/// assert_eq!(buffer[..5], [9, 0, 0, 0, 5]);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ByteOrder {
+    /// Least significant byte first (the default)
+    #[default]
+    LittleEndian,
+    /// Most significant byte first
+    BigEndian,
+}
+
+impl ByteOrder {
+    #[cfg(feature="writing")]
+    fn uint_to_bytes(self, value: u64, width: usize, out: &mut Vec<u8>) {
+        match self {
+            ByteOrder::LittleEndian => out.extend_from_slice(&value.to_le_bytes()[..width]),
+            ByteOrder::BigEndian => out.extend_from_slice(&value.to_be_bytes()[8 - width..]),
+        }
+    }
+
+    #[cfg(feature="reading")]
+    fn uint_from_bytes(self, bytes: &[u8]) -> u64 {
+        let mut buf = [0; 8];
+        match self {
+            ByteOrder::LittleEndian => {
+                buf[..bytes.len()].copy_from_slice(bytes);
+                u64::from_le_bytes(buf)
+            }
+            ByteOrder::BigEndian => {
+                buf[8 - bytes.len()..].copy_from_slice(bytes);
+                u64::from_be_bytes(buf)
+            }
+        }
+    }
+}
+
+/// How a length is encoded on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LengthPrefix {
+    /// A LEB128 variable-length integer of 1 to 10 bytes
+    Varint,
+    /// A single byte, for lengths up to 255
+    U8,
+    /// Two bytes, for lengths up to 64 KiB
+    U16,
+    /// Four bytes, for lengths up to 4 GiB
+    U32,
+    /// Eight bytes
+    U64,
+}
+
+impl LengthPrefix {
+    /// Returns the largest length this prefix can encode
+    pub fn max_length(self) -> u64 {
+        match self {
+            LengthPrefix::Varint | LengthPrefix::U64 => u64::MAX,
+            LengthPrefix::U8 => u8::MAX as u64,
+            LengthPrefix::U16 => u16::MAX as u64,
+            LengthPrefix::U32 => u32::MAX as u64,
+        }
+    }
+
+    /// Returns the number of bytes this prefix takes to encode the given length
+    pub fn encoded_len(self, length: u64) -> usize {
+        match self {
+            LengthPrefix::Varint => (64 - (length | 1).leading_zeros() as usize).div_ceil(7),
+            LengthPrefix::U8 => 1,
+            LengthPrefix::U16 => 2,
+            LengthPrefix::U32 => 4,
+            LengthPrefix::U64 => 8,
+        }
+    }
+
+    /// Returns the fixed width of this prefix, or None for varints
+    #[cfg(any(feature="reading", feature="writing"))]
+    pub(crate) fn width(self) -> Option<usize> {
+        match self {
+            LengthPrefix::Varint => None,
+            fixed => Some(fixed.encoded_len(0)),
+        }
+    }
+
+    /// Checks that the given length can be encoded with this prefix
+    #[cfg(feature="writing")]
+    pub(crate) fn check(self, length: u64) -> Result<()> {
+        if length > self.max_length() {
+            return Err(Error::LengthOverflow { length, max: self.max_length() });
+        }
+        Ok(())
+    }
+
+    /// Appends the encoded length to `out`
+    #[cfg(feature="writing")]
+    pub(crate) fn encode(self, length: u64, byte_order: ByteOrder, out: &mut Vec<u8>) -> Result<()> {
+        self.check(length)?;
+        match self.width() {
+            Some(width) => byte_order.uint_to_bytes(length, width, out),
+            None => {
+                let mut value = length;
+                loop {
+                    let mut byte = (value & 0x7F) as u8;
+                    value >>= 7;
+                    if value != 0 {
+                        byte |= 0x80;
+                    }
+                    out.push(byte);
+                    if value == 0 {
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Describes the wire format of a frame: how the frame's total size and each segment's length are
+/// encoded, and the byte order of fixed-width lengths.
+/// * Both peers must use the same format.
+///
+/// The default format is a varint total size followed by little-endian `u32` segment lengths.
+/// # Example
+/// ```
+/// use send_it::{FrameFormat, LengthPrefix};
+/// use send_it::reader::VarReader;
+/// use send_it::writer::VarWriter;
+///
+/// // compact varint segment lengths for many tiny segments
+/// let format = FrameFormat { segment_length: LengthPrefix::Varint, ..FrameFormat::default() };
+///
+/// let mut sender = VarWriter::with_format(format);
+/// sender.add_string("Hello");
+/// sender.add_string("World");
+///
+/// let mut buffer = Vec::new();
+/// sender.send(&mut buffer).unwrap();
+/// assert_eq!(buffer.len(), 13);
+///
+/// let mut fake_stream = buffer.as_slice();
+/// let mut reader = VarReader::new(&mut fake_stream);
+/// reader.set_format(format);
+/// assert_eq!(reader.read_data().unwrap().unwrap()[1].to_string(), "World");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameFormat {
+    /// How the total size of a frame is encoded
+    pub frame_length: LengthPrefix,
+    /// How the length of each segment is encoded
+    pub segment_length: LengthPrefix,
+    /// The byte order of fixed-width lengths
+    pub byte_order: ByteOrder,
+}
+
+impl FrameFormat {
+    /// Creates the default FrameFormat: a varint total size and little-endian `u32` segment lengths
+    pub fn new() -> Self {
+        Self {
+            frame_length: LengthPrefix::Varint,
+            segment_length: LengthPrefix::U32,
+            byte_order: ByteOrder::LittleEndian,
+        }
+    }
+
+    /// Returns the number of bytes a segment of the given length takes up in a frame, including its length prefix
+    #[cfg(feature="writing")]
+    pub(crate) fn segment_size(&self, length: usize) -> Result<usize> {
+        let length = length as u64;
+        self.segment_length.check(length)?;
+        let prefix_len = self.segment_length.encoded_len(length);
+        prefix_len.checked_add(length as usize)
+            .ok_or(Error::LengthOverflow { length: length.saturating_add(prefix_len as u64), max: usize::MAX as u64 })
+    }
+
+    /// Encodes the length of a frame of the given total size
+    #[cfg(feature="writing")]
+    pub(crate) fn encode_frame_length(&self, total_size: usize, out: &mut Vec<u8>) -> Result<()> {
+        self.frame_length.encode(total_size as u64, self.byte_order, out)
+    }
+
+    /// Encodes the length of a segment
+    #[cfg(feature="writing")]
+    pub(crate) fn encode_segment_length(&self, length: usize, out: &mut Vec<u8>) -> Result<()> {
+        self.segment_length.encode(length as u64, self.byte_order, out)
+    }
+
+    /// Decodes a fixed-width length
+    #[cfg(feature="reading")]
+    pub(crate) fn decode_fixed(&self, bytes: &[u8]) -> u64 {
+        self.byte_order.uint_from_bytes(bytes)
+    }
+}
+
+impl Default for FrameFormat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Incrementally decodes a LEB128 varint one byte at a time
+#[cfg(feature="reading")]
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct VarintDecoder {
+    value: u64,
+    shift: u32,
+}
+
+#[cfg(feature="reading")]
+impl VarintDecoder {
+    /// Adds the next byte of the varint, returning the value once its last byte has been pushed
+    pub(crate) fn push(&mut self, byte: u8, mode: crate::DecodeMode) -> Result<Option<u64>> {
+        let bits = (byte & 0x7F) as u64;
+        if self.shift >= u64::BITS || bits << self.shift >> self.shift != bits {
+            return Err(Error::VarintOverflow);
+        }
+        self.value |= bits << self.shift;
+        if byte & 0x80 != 0 {
+            self.shift += 7;
+            return Ok(None);
+        }
+        // the last byte of an over-long varint adds no bits to the value
+        if mode == crate::DecodeMode::Strict && byte == 0 && self.shift > 0 {
+            return Err(Error::NonCanonicalVarint);
+        }
+        let value = self.value;
+        *self = Self::default();
+        Ok(Some(value))
+    }
+}
+
+/// Converts a decoded length to usize, saturating so that the reader's limits reject it
+#[cfg(feature="reading")]
+pub(crate) fn to_usize(length: u64) -> usize {
+    usize::try_from(length).unwrap_or(usize::MAX)
+}
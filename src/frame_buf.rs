@@ -0,0 +1,115 @@
+use std::ops::Index;
+
+#[cfg(feature="bytes")]
+use crate::Segment;
+
+/// A frame whose segments are stored back to back in one buffer, filled by
+/// [`VarReader::read_data_into`](crate::reader::VarReader::read_data_into).
+/// Reusing the same FrameBuf for every frame avoids allocating once the buffer has grown to fit the largest frame.
+/// # Example
+/// ```
+/// use send_it::reader::VarReader;
+/// use send_it::FrameBuf;
+///
+/// let stream: Vec<u8> = vec![21, 7, 0, 0, 0, 72, 101, 108, 108, 111, 44, 32, 6, 0, 0, 0, 87, 111, 114, 108, 100, 33];
+/// let mut fake_stream = stream.as_slice();
+/// let mut reader = VarReader::new(&mut fake_stream);
+///
+/// let mut frame = FrameBuf::new();
+/// while reader.read_data_into(&mut frame).unwrap() {
+///     assert_eq!(frame.len(), 2);
+///     assert_eq!(&frame[0], b"Hello, ");
+///     assert_eq!(frame.get(1), Some(&b"World!"[..]));
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct FrameBuf {
+    data: Vec<u8>,
+    /// The end of each segment in `data`
+    ends: Vec<usize>,
+}
+
+impl FrameBuf {
+    /// Creates a new, empty FrameBuf
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of segments in the frame
+    pub fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    /// Returns true if the frame has no segments
+    pub fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
+
+    /// Returns the data of the segment at `index`, or `None` if it is out of bounds
+    pub fn get(&self, index: usize) -> Option<&[u8]> {
+        let end = *self.ends.get(index)?;
+        let start = if index == 0 { 0 } else { self.ends[index - 1] };
+        Some(&self.data[start..end])
+    }
+
+    /// Returns an iterator over the data of each segment
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        let starts = std::iter::once(0).chain(self.ends.iter().copied());
+        starts.zip(&self.ends).map(|(start, end)| &self.data[start..*end])
+    }
+
+    /// Returns the data of every segment, back to back
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Removes every segment, keeping the allocated memory
+    pub fn clear(&mut self) {
+        self.data.clear();
+        self.ends.clear();
+    }
+
+    /// Turns the frame into Segments that all share its buffer, without copying their data.
+    /// # Example
+    /// ```
+    /// use send_it::reader::VarReader;
+    /// use send_it::FrameBuf;
+    ///
+    /// let stream: Vec<u8> = vec![21, 7, 0, 0, 0, 72, 101, 108, 108, 111, 44, 32, 6, 0, 0, 0, 87, 111, 114, 108, 100, 33];
+    /// let mut fake_stream = stream.as_slice();
+    /// let mut reader = VarReader::new(&mut fake_stream);
+    ///
+    /// let mut frame = FrameBuf::new();
+    /// reader.read_data_into(&mut frame).unwrap();
+    ///
+    /// let segments = frame.into_segments();
+    /// assert_eq!(segments[1].to_string(), "World!");
+    /// ```
+    #[cfg(feature="bytes")]
+    pub fn into_segments(self) -> Vec<Segment> {
+        let data = bytes::Bytes::from(self.data);
+        let starts = std::iter::once(0).chain(self.ends.iter().copied());
+        starts.zip(&self.ends).map(|(start, end)| Segment::from(data.slice(start..*end))).collect()
+    }
+
+    /// Returns the buffer the next segment's data is appended to
+    pub(crate) fn data_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.data
+    }
+
+    /// Marks everything appended to the buffer since the last segment as a new segment
+    pub(crate) fn end_segment(&mut self) {
+        self.ends.push(self.data.len());
+    }
+}
+
+impl Index<usize> for FrameBuf {
+    type Output = [u8];
+
+    fn index(&self, index: usize) -> &[u8] {
+        match self.get(index) {
+            Some(segment) => segment,
+            None => panic!("segment index {} out of range for a frame of {} segments", index, self.len()),
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use std::io::{Read, Write};
+
+use crate::reader::ReaderCore;
+use crate::writer::send_frame;
+use crate::{Result, Segment};
+
+/// A stream that both sends and reads frames, owning the underlying stream.
+/// Unlike VarReader, which borrows its stream, a FramedStream can be stored next to other connection state
+/// or moved into another thread.
+/// * Frames are read and written in the same [`FrameFormat`](crate::FrameFormat).
+/// * Reads are buffered, so reading from the stream returned by `get_mut` skips any bytes already buffered.
+/// # Example
+/// ```
+/// use std::io::Cursor;
+/// use send_it::framed::FramedStream;
+/// use send_it::Segment;
+///
+/// let mut framed = FramedStream::new(Cursor::new(Vec::new()));
+/// framed.send(&[Segment::from("Hello, "), Segment::from("World!")]).unwrap();
+///
+/// // rewind the cursor to read back what was sent
+/// framed.get_mut().set_position(0);
+/// let data = framed.read_data().unwrap().unwrap();
+/// assert_eq!(data[1].to_string(), "World!");
+/// ```
+pub struct FramedStream<S> {
+    core: ReaderCore<S>,
+}
+
+/// Implements the constructors and accessors shared by the blocking and async FramedStreams,
+/// which all wrap the `ReaderCore` in scope where the macro is used.
+macro_rules! framed_stream_accessors {
+    () => {
+        impl<S> FramedStream<S> {
+            /// Create a new FramedStream with the default [`ReaderConfig`](crate::ReaderConfig) limits
+            pub fn new(stream: S) -> Self {
+                Self::with_config(stream, $crate::ReaderConfig::default())
+            }
+
+            /// Create a new FramedStream that enforces the given limits on incoming frames
+            pub fn with_config(stream: S, config: $crate::ReaderConfig) -> Self {
+                let mut framed = Self::with_capacity(stream, $crate::reader::DEFAULT_BUFFER_CAPACITY);
+                framed.core.decoder.set_config(config);
+                framed
+            }
+
+            /// Create a new FramedStream whose read buffer holds `capacity` bytes
+            pub fn with_capacity(stream: S, capacity: usize) -> Self {
+                FramedStream { core: ReaderCore::new(stream, capacity) }
+            }
+
+            /// Returns the bytes read from the stream that have not been decoded yet
+            pub fn buffer(&self) -> &[u8] {
+                self.core.buffer()
+            }
+
+            /// Unwraps the FramedStream, returning the stream and the bytes read from it that have not been decoded yet
+            pub fn into_parts(self) -> (S, Vec<u8>) {
+                self.core.into_parts()
+            }
+
+            /// Returns a reference to the underlying stream
+            pub fn get_ref(&self) -> &S {
+                &self.core.reader
+            }
+
+            /// Returns a mutable reference to the underlying stream
+            pub fn get_mut(&mut self) -> &mut S {
+                &mut self.core.reader
+            }
+
+            /// Unwraps the FramedStream, returning the underlying stream.
+            /// * Bytes that were read ahead but not decoded yet are lost, use `into_parts` to keep them.
+            pub fn into_inner(self) -> S {
+                self.core.reader
+            }
+
+            /// Returns the limits this stream enforces on incoming frames
+            pub fn config(&self) -> &$crate::ReaderConfig {
+                self.core.decoder.config()
+            }
+
+            /// Replaces the limits this stream enforces on incoming frames
+            pub fn set_config(&mut self, config: $crate::ReaderConfig) {
+                self.core.decoder.set_config(config);
+            }
+
+            /// Returns the format frames are read and written in
+            pub fn format(&self) -> &$crate::FrameFormat {
+                self.core.decoder.format()
+            }
+
+            /// Sets the format frames are read and written in, which must match the peer's
+            pub fn set_format(&mut self, format: $crate::FrameFormat) {
+                self.core.decoder.set_format(format);
+            }
+
+            /// Returns the byte order fixed-width lengths are read and written in
+            pub fn byte_order(&self) -> $crate::ByteOrder {
+                self.core.decoder.byte_order()
+            }
+
+            /// Sets the byte order fixed-width lengths are read and written in, which must match the peer's
+            pub fn set_byte_order(&mut self, byte_order: $crate::ByteOrder) {
+                self.core.decoder.set_byte_order(byte_order);
+            }
+        }
+    };
+}
+
+#[cfg(any(feature="async-tokio", feature="futures-io"))]
+pub(crate) use framed_stream_accessors;
+
+framed_stream_accessors!();
+
+impl<S: Read> FramedStream<S> {
+    /// Reads a frame from the stream.
+    /// * Returns `Ok(None)` if the stream ended cleanly between two frames.
+    /// * Returns [`Error::TruncatedFrame`](crate::Error::TruncatedFrame) if the stream ended in the middle of a frame.
+    pub fn read_data(&mut self) -> Result<Option<Vec<Segment>>> {
+        self.core.read_data()
+    }
+}
+
+impl<S: Write> FramedStream<S> {
+    /// Encodes the segments as one frame and sends it over the stream
+    pub fn send(&mut self, segments: &[Segment]) -> Result<()> {
+        send_frame(self.core.decoder.format(), segments, &mut self.core.reader)
+    }
+}
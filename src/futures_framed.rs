@@ -0,0 +1,51 @@
+use std::future::poll_fn;
+
+use futures_util::io::{AsyncRead, AsyncWrite};
+
+use crate::poll_io::futures_io::{poll_read, poll_write_vectored};
+use crate::poll_reader::ReaderCore;
+use crate::poll_writer::send_frame;
+use crate::{Result, Segment};
+
+/// A stream that both sends and reads frames, owning the underlying stream.
+/// Unlike VarReader, which borrows its stream, a FramedStream can be stored next to other connection state
+/// or moved into a spawned task.
+/// * Frames are read and written in the same [`FrameFormat`](crate::FrameFormat).
+/// # Example
+/// ```
+/// # futures::executor::block_on(async {
+/// use futures::io::Cursor;
+/// use send_it::futures_framed::FramedStream;
+/// use send_it::Segment;
+///
+/// let mut framed = FramedStream::new(Cursor::new(Vec::new()));
+/// framed.send(&[Segment::from("Hello, "), Segment::from("World!")]).await.unwrap();
+///
+/// // rewind the cursor to read back what was sent
+/// framed.get_mut().set_position(0);
+/// let data = framed.read_data().await.unwrap().unwrap();
+/// assert_eq!(data[1].to_string(), "World!");
+/// # });
+/// ```
+pub struct FramedStream<S> {
+    core: ReaderCore<S>,
+}
+
+crate::framed::framed_stream_accessors!();
+
+impl<S: AsyncRead + Unpin> FramedStream<S> {
+    /// Reads a frame from the stream.
+    /// * Returns `Ok(None)` if the stream ended cleanly between two frames.
+    /// * Returns [`Error::TruncatedFrame`](crate::Error::TruncatedFrame) if the stream ended in the middle of a frame.
+    /// * Cancel safe: a partially read frame is kept if the future is dropped, so it can be used in `select!`.
+    pub async fn read_data(&mut self) -> Result<Option<Vec<Segment>>> {
+        poll_fn(|cx| self.core.poll_read_data(cx, poll_read)).await
+    }
+}
+
+impl<S: AsyncWrite + Unpin> FramedStream<S> {
+    /// Encodes the segments as one frame and sends it over the stream
+    pub async fn send(&mut self, segments: &[Segment]) -> Result<()> {
+        send_frame(self.core.decoder.format(), segments, &mut self.core.reader, poll_write_vectored).await
+    }
+}
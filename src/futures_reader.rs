@@ -0,0 +1,22 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use futures_util::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+
+use crate::poll_io::futures_io::poll_read;
+use crate::poll_reader::ReaderCore;
+
+crate::poll_reader::var_reader! {
+    runtime: "futures-io",
+    module: "futures_reader",
+    block_on: "futures::executor::block_on(async {",
+    read_ext: "futures::AsyncReadExt",
+    select: "select!",
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for SegmentReader<'_, R> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        Poll::Ready(Ok(ready!(self.get_mut().core.poll_read_segment(cx, poll_read, buf))?))
+    }
+}
@@ -0,0 +1,9 @@
+use futures_util::io::{AsyncRead, AsyncWrite};
+use crate::poll_io::futures_io::{poll_read, poll_write_vectored};
+
+crate::poll_writer::var_writer! {
+    runtime: "futures-io",
+    module: "futures_writer",
+    block_on: "futures::executor::block_on(async {",
+    cursor: "futures::io::Cursor",
+}
@@ -1,20 +1,68 @@
 use std::fmt::Display;
 
+pub mod config;
+#[cfg(feature="writing")]
+mod encode;
+mod error;
+mod format;
+#[cfg(feature="reading")]
+mod frame_buf;
+
+pub use config::{DecodeMode, ReaderConfig};
+pub use error::{Error, Result};
+pub use format::{ByteOrder, FrameFormat, LengthPrefix};
+#[cfg(feature="reading")]
+pub use frame_buf::FrameBuf;
+#[cfg(feature="writing")]
+pub use encode::{encode_frame, encoded_len};
+#[cfg(feature="reading")]
+pub use decoder::decode_frame;
+
 #[cfg(feature="writing")]
-#[cfg(not(feature="tokio"))]
 pub mod writer;
 #[cfg(feature="reading")]
-#[cfg(not(feature="tokio"))]
+pub mod decoder;
+#[cfg(feature="reading")]
 pub mod reader;
+#[cfg(any(feature="reading", feature="writing"))]
+#[cfg(any(feature="async-tokio", feature="futures-io"))]
+mod poll_io;
+#[cfg(feature="reading")]
+#[cfg(any(feature="async-tokio", feature="futures-io"))]
+mod poll_reader;
+#[cfg(feature="writing")]
+#[cfg(any(feature="async-tokio", feature="futures-io"))]
+mod poll_writer;
+
+#[cfg(all(feature="reading", feature="writing"))]
+pub mod framed;
 
 #[cfg(feature="writing")]
-#[cfg(feature="tokio")]
+#[cfg(feature="async-tokio")]
 pub mod async_writer;
 #[cfg(feature="reading")]
-#[cfg(feature="tokio")]
+#[cfg(feature="async-tokio")]
 pub mod async_reader;
+#[cfg(all(feature="reading", feature="writing"))]
+#[cfg(feature="async-tokio")]
+pub mod async_framed;
+#[cfg(all(feature="reading", feature="writing"))]
+#[cfg(feature="codec")]
+pub mod codec;
+
+#[cfg(feature="writing")]
+#[cfg(feature="futures-io")]
+pub mod futures_writer;
+#[cfg(feature="reading")]
+#[cfg(feature="futures-io")]
+pub mod futures_reader;
+#[cfg(all(feature="reading", feature="writing"))]
+#[cfg(feature="futures-io")]
+pub mod futures_framed;
 
 /// A segment of data used by VarReader and VarWriter to send and receive data over a stream.
+/// * With the `bytes` feature a Segment is backed by `bytes::Bytes`, so clones and slices share its data
+///   instead of copying it, such as when fanning one frame out to many subscribers.
 /// # Examples
 /// ```
 /// use send_it::Segment;
@@ -27,14 +75,20 @@ pub mod async_reader;
 /// ```
 #[derive(Debug, Clone)]
 pub struct Segment {
-    seg: Vec<u8>
+    seg: SegmentData
 }
 
+/// The data behind a Segment, shared between clones with the `bytes` feature
+#[cfg(not(feature="bytes"))]
+type SegmentData = Vec<u8>;
+#[cfg(feature="bytes")]
+type SegmentData = bytes::Bytes;
+
 impl Segment {
     /// Creates a new Segment.
     pub fn new() -> Self {
         Self {
-            seg: Vec::new()
+            seg: SegmentData::new()
         }
     }
 
@@ -44,19 +98,44 @@ impl Segment {
 
     /// Appends a Segment to the end of this Segment.
     pub fn append(&mut self, seg: Segment) {
+        #[cfg(not(feature="bytes"))]
         self.seg.extend(seg.seg);
+        #[cfg(feature="bytes")]
+        {
+            let mut data = Vec::from(std::mem::take(&mut self.seg));
+            data.extend_from_slice(&seg.seg);
+            self.seg = data.into();
+        }
     }
 
+    #[cfg(feature="writing")]
     pub(crate) fn len(&self) -> usize {
         self.seg.len()
     }
 
     pub fn to_raw(&self) -> Vec<u8> {
-        self.seg.clone()
+        self.seg.to_vec()
     }
     
 }
 
+#[cfg(feature="bytes")]
+impl Segment {
+    /// Returns the given range of the segment as a new Segment sharing its data, without copying it.
+    /// # Example
+    /// ```
+    /// use send_it::Segment;
+    ///
+    /// let segment = Segment::from("Hello, World!");
+    /// assert_eq!(segment.slice(7..).to_string(), "World!");
+    /// ```
+    pub fn slice(&self, range: impl std::ops::RangeBounds<usize>) -> Segment {
+        Self {
+            seg: self.seg.slice(range)
+        }
+    }
+}
+
 impl Default for Segment {
     fn default() -> Self {
         Self::new()
@@ -71,34 +150,70 @@ impl AsRef<[u8]> for Segment {
 
 impl From<&[u8]> for Segment {
     fn from(value: &[u8]) -> Self {
-        Self {
-            seg: value.to_vec()
-        }
+        Self::from(value.to_vec())
     }
 }
 
 impl From<Vec<u8>> for Segment {
     fn from(value: Vec<u8>) -> Self {
+        #[cfg(feature="bytes")]
+        let value = bytes::Bytes::from(value);
         Self {
             seg: value
         }
     }
 }
 
+impl From<&str> for Segment {
+    fn from(value: &str) -> Self {
+        Self::from(value.as_bytes())
+    }
+}
+
 impl From<String> for Segment {
     fn from(value: String) -> Self {
+        Self::from(value.into_bytes())
+    }
+}
+
+#[cfg(not(feature="bytes"))]
+impl From<Segment> for Vec<u8> {
+    fn from(value: Segment) -> Self {
+        value.seg
+    }
+}
+
+/// Takes the segment's data back without copying it, unless it is shared with a clone or a slice.
+#[cfg(feature="bytes")]
+impl From<Segment> for Vec<u8> {
+    fn from(value: Segment) -> Self {
+        value.seg.into()
+    }
+}
+
+#[cfg(feature="bytes")]
+impl From<bytes::Bytes> for Segment {
+    fn from(value: bytes::Bytes) -> Self {
         Self {
-            seg: value.as_bytes().to_vec()
+            seg: value
         }
     }
 }
 
+#[cfg(feature="bytes")]
+impl From<Segment> for bytes::Bytes {
+    fn from(value: Segment) -> Self {
+        value.seg
+    }
+}
+
 impl Display for Segment {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", String::from_utf8_lossy(&self.seg))
     }
 }
 
+#[cfg(test)]
 mod tests {
 
     #[test]
@@ -128,7 +243,7 @@ mod tests {
         let mut reader = crate::reader::VarReader::new(&mut fake_stream);
 
         // read the data from the stream
-        let data = reader.read_data().unwrap();
+        let data = reader.read_data().unwrap().unwrap();
         assert_eq!(data[0].to_string(), "Hello, ");
         assert_eq!(data[1].to_string(), "World!");
     }
@@ -155,8 +270,1261 @@ mod tests {
         let mut reader = crate::reader::VarReader::new(&mut fake_stream);
 
         // read the data from the stream
-        let data = reader.read_data().unwrap();
+        let data = reader.read_data().unwrap().unwrap();
         assert_eq!(data[0].to_string(), "Hello, ");
         assert_eq!(data[1].to_string(), "World!");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn reader_limits() {
+        use crate::{Error, ReaderConfig};
+
+        // a frame header declaring a frame of u32::MAX bytes
+        let stream: Vec<u8> = vec![0xFF, 0xFF, 0xFF, 0xFF, 0x0F];
+        let mut fake_stream = stream.as_slice();
+        let config = ReaderConfig { max_frame_size: 1024, ..ReaderConfig::default() };
+        let mut reader = crate::reader::VarReader::with_config(&mut fake_stream, config);
+        assert!(matches!(reader.read_data(), Err(Error::FrameTooLarge { size, max: 1024 }) if size == u32::MAX as usize));
+
+        // a segment header declaring a 4 GiB segment, with no payload behind it
+        let stream: Vec<u8> = vec![0x83, 0x80, 0x80, 0x80, 0x10, 0xFF, 0xFF, 0xFF, 0xFF];
+        let mut fake_stream = stream.as_slice();
+        let config = ReaderConfig { max_segment_size: 1024, ..ReaderConfig::unlimited() };
+        let mut reader = crate::reader::VarReader::with_config(&mut fake_stream, config);
+        assert!(matches!(reader.read_data(), Err(Error::SegmentTooLarge { size, max: 1024 }) if size == u32::MAX as usize));
+
+        // without a segment limit the lying header only fails once the stream runs dry
+        let mut fake_stream = stream.as_slice();
+        let mut reader = crate::reader::VarReader::with_config(&mut fake_stream, ReaderConfig::unlimited());
+        assert!(matches!(reader.read_data(), Err(Error::TruncatedFrame)));
+
+        // too many segments
+        let stream: Vec<u8> = vec![12, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let mut fake_stream = stream.as_slice();
+        let config = ReaderConfig { max_segment_count: 2, ..ReaderConfig::default() };
+        let mut reader = crate::reader::VarReader::with_config(&mut fake_stream, config);
+        assert!(matches!(reader.read_data(), Err(Error::TooManySegments { max: 2 })));
+    }
+
+    #[test]
+    fn reader_errors() {
+        use crate::Error;
+
+        // a varint with more continuation bytes than fit in 64 bits
+        let stream: Vec<u8> = vec![0xFF; 11];
+        let mut fake_stream = stream.as_slice();
+        let mut reader = crate::reader::VarReader::new(&mut fake_stream);
+        let err = reader.read_data().unwrap_err();
+        assert!(matches!(err, Error::VarintOverflow));
+        assert!(err.is_protocol_error());
+
+        // a header cut off in the middle of a segment length
+        let stream: Vec<u8> = vec![11, 7, 0];
+        let mut fake_stream = stream.as_slice();
+        let mut reader = crate::reader::VarReader::new(&mut fake_stream);
+        let err = reader.read_data().unwrap_err();
+        assert!(matches!(err, Error::TruncatedFrame));
+        assert!(!err.is_protocol_error());
+        assert_eq!(std::io::Error::from(err).kind(), std::io::ErrorKind::UnexpectedEof);
+
+        // a segment too long for its length prefix is the sender's mistake, not the peer's
+        let format = crate::FrameFormat { segment_length: crate::LengthPrefix::U8, ..crate::FrameFormat::default() };
+        let mut writer = crate::writer::VarWriter::with_format(format);
+        writer.add(crate::Segment::from(vec![0u8; 256]));
+        let err = writer.send(&mut Vec::new()).unwrap_err();
+        assert!(matches!(err, Error::LengthOverflow { length: 256, max: 255 }));
+        assert!(!err.is_protocol_error());
+        assert_eq!(std::io::Error::from(err).kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn strict_decoding() {
+        use crate::{DecodeMode, Error, ReaderConfig};
+
+        let lenient = ReaderConfig { mode: DecodeMode::Lenient, ..ReaderConfig::default() };
+
+        // a frame declaring 6 bytes whose segment spans 11
+        let stream: Vec<u8> = vec![6, 7, 0, 0, 0, 72, 101, 108, 108, 111, 44, 32];
+        let mut fake_stream = stream.as_slice();
+        let mut reader = crate::reader::VarReader::new(&mut fake_stream);
+        assert!(matches!(reader.read_data(), Err(Error::InconsistentSize { declared: 6, actual: 11 })));
+
+        let mut fake_stream = stream.as_slice();
+        let mut reader = crate::reader::VarReader::with_config(&mut fake_stream, lenient);
+        assert_eq!(reader.read_data().unwrap().unwrap()[0].to_string(), "Hello, ");
+
+        // the total size 11 encoded with a redundant continuation byte
+        let stream: Vec<u8> = vec![0x8B, 0x00, 7, 0, 0, 0, 72, 101, 108, 108, 111, 44, 32];
+        let mut fake_stream = stream.as_slice();
+        let mut reader = crate::reader::VarReader::new(&mut fake_stream);
+        assert!(matches!(reader.read_data(), Err(Error::NonCanonicalVarint)));
+
+        let mut fake_stream = stream.as_slice();
+        let mut reader = crate::reader::VarReader::with_config(&mut fake_stream, lenient);
+        assert_eq!(reader.read_data().unwrap().unwrap()[0].to_string(), "Hello, ");
+
+        // varints overflowing 64 bits are rejected in both modes
+        let stream: Vec<u8> = vec![0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x02];
+        let mut fake_stream = stream.as_slice();
+        let mut reader = crate::reader::VarReader::with_config(&mut fake_stream, lenient);
+        assert!(matches!(reader.read_data(), Err(Error::VarintOverflow)));
+    }
+
+    #[test]
+    fn reader_eof() {
+        use crate::Error;
+
+        // an empty stream ends cleanly
+        let mut fake_stream: &[u8] = &[];
+        let mut reader = crate::reader::VarReader::new(&mut fake_stream);
+        assert!(reader.read_data().unwrap().is_none());
+
+        // a stream ending right after a frame ends cleanly
+        let stream: Vec<u8> = vec![10, 6, 0, 0, 0, 87, 111, 114, 108, 100, 33];
+        let mut fake_stream = stream.as_slice();
+        let mut reader = crate::reader::VarReader::new(&mut fake_stream);
+        assert_eq!(reader.read_data().unwrap().unwrap()[0].to_string(), "World!");
+        assert!(reader.read_data().unwrap().is_none());
+
+        // a stream ending inside the varint, the segment length or the payload is truncated
+        let stream: Vec<u8> = vec![10, 6, 0, 0, 0, 87, 111, 114, 108, 100, 33];
+        for mut fake_stream in [&[0x8A][..], &stream[..3], &stream[..8]] {
+            let mut reader = crate::reader::VarReader::new(&mut fake_stream);
+            assert!(matches!(reader.read_data(), Err(Error::TruncatedFrame)));
+        }
+    }
+
+    #[test]
+    fn frame_decoder() {
+        use crate::decoder::FrameDecoder;
+        use crate::{Error, ReaderConfig};
+
+        let mut writer = crate::writer::VarWriter::new();
+        writer.add_string("Hello, ");
+        writer.add_raw(&[]);
+        writer.add_string("World!");
+        let mut stream: Vec<u8> = Vec::new();
+        writer.send_without_clearing(&mut stream).unwrap();
+        writer.send(&mut stream).unwrap();
+
+        // the same frames come out whether the stream arrives all at once or a byte at a time
+        for chunk_size in [stream.len(), 7, 1] {
+            let mut decoder = FrameDecoder::new();
+            let mut frames = Vec::new();
+            for chunk in stream.chunks(chunk_size) {
+                decoder.feed(chunk);
+                while let Some(frame) = decoder.decode().unwrap() {
+                    frames.push(frame);
+                }
+            }
+            decoder.finish().unwrap();
+            assert_eq!(frames.len(), 2);
+            for frame in frames {
+                let frame: Vec<String> = frame.iter().map(|segment| segment.to_string()).collect();
+                assert_eq!(frame, ["Hello, ", "", "World!"]);
+            }
+        }
+
+        // a stream ending mid-frame is truncated
+        let mut decoder = FrameDecoder::new();
+        decoder.feed(&stream[..3]);
+        assert!(decoder.decode().unwrap().is_none());
+        assert!(decoder.is_mid_frame());
+        assert!(matches!(decoder.finish(), Err(Error::TruncatedFrame)));
+
+        // limits are enforced as soon as the header arrives
+        let config = ReaderConfig { max_frame_size: 8, ..ReaderConfig::default() };
+        let mut decoder = FrameDecoder::with_config(config);
+        decoder.feed(&stream[..1]);
+        assert!(matches!(decoder.decode(), Err(Error::FrameTooLarge { max: 8, .. })));
+    }
+
+    #[test]
+    fn reader_frames() {
+        use crate::Error;
+
+        let mut writer = crate::writer::VarWriter::new();
+        let mut stream: Vec<u8> = Vec::new();
+        for i in 0..5 {
+            writer.add_string(format!("frame {i}"));
+            writer.send(&mut stream).unwrap();
+        }
+
+        let mut fake_stream = stream.as_slice();
+        let mut reader = crate::reader::VarReader::with_capacity(&mut fake_stream, 4);
+        let frames: Vec<String> = reader.frames().map(|frame| frame.unwrap()[0].to_string()).collect();
+        assert_eq!(frames, ["frame 0", "frame 1", "frame 2", "frame 3", "frame 4"]);
+        // the iterator ended at EOF and the reader stays at EOF
+        assert!(reader.frames().next().is_none());
+
+        // a truncated last frame is yielded as an error, then the iterator ends
+        let mut fake_stream = &stream[..stream.len() - 1];
+        let mut reader = crate::reader::VarReader::new(&mut fake_stream);
+        let mut frames = reader.frames();
+        assert_eq!(frames.by_ref().take(4).filter(Result::is_ok).count(), 4);
+        assert!(matches!(frames.next(), Some(Err(Error::TruncatedFrame))));
+        assert!(frames.next().is_none());
+
+        let mut fake_stream = &stream[..stream.len() - 1];
+        assert!(crate::reader::VarReader::new(&mut fake_stream).read_all().is_err());
+        let mut fake_stream = stream.as_slice();
+        assert_eq!(crate::reader::VarReader::new(&mut fake_stream).read_all().unwrap().len(), 5);
+    }
+
+    #[test]
+    fn lazy_segments() {
+        use std::io::Read;
+
+        let large = vec![7u8; 20_000];
+        let mut writer = crate::writer::VarWriter::new();
+        writer.add_string("Hello, ");
+        writer.add(crate::Segment::from(large.clone()));
+        writer.add_raw(&[]);
+        writer.add_string("World!");
+        let mut stream: Vec<u8> = Vec::new();
+        writer.send(&mut stream).unwrap();
+        writer.add_string("next");
+        writer.send(&mut stream).unwrap();
+
+        let mut fake_stream = stream.as_slice();
+        let mut reader = crate::reader::VarReader::with_capacity(&mut fake_stream, 64);
+        {
+            let mut frame = reader.next_frame().unwrap().unwrap();
+            let mut segment = frame.next_segment().unwrap().unwrap();
+            let mut hello = String::new();
+            segment.read_to_string(&mut hello).unwrap();
+            assert_eq!(hello, "Hello, ");
+            drop(segment);
+
+            // half of the large segment is read, the rest is skipped when it is dropped
+            let mut segment = frame.next_segment().unwrap().unwrap();
+            assert_eq!(segment.len(), large.len());
+            let mut half = vec![0; large.len() / 2];
+            segment.read_exact(&mut half).unwrap();
+            assert_eq!(half, large[..large.len() / 2]);
+            assert_eq!(segment.remaining(), large.len() / 2);
+            drop(segment);
+
+            let segment = frame.next_segment().unwrap().unwrap();
+            assert!(segment.is_empty());
+            // the last segment is never visited
+        }
+        assert_eq!(reader.read_data().unwrap().unwrap()[0].to_string(), "next");
+        assert!(reader.next_frame().unwrap().is_none());
+
+        // every segment of a frame read to the end
+        let mut fake_stream = stream.as_slice();
+        let mut reader = crate::reader::VarReader::new(&mut fake_stream);
+        let mut frame = reader.next_frame().unwrap().unwrap();
+        let mut segments = Vec::new();
+        while let Some(mut segment) = frame.next_segment().unwrap() {
+            let mut data = Vec::new();
+            segment.read_to_end(&mut data).unwrap();
+            segments.push(data);
+        }
+        assert_eq!(segments, [b"Hello, ".to_vec(), large.clone(), Vec::new(), b"World!".to_vec()]);
+        assert!(frame.next_segment().unwrap().is_none());
+        drop(frame);
+        assert!(reader.next_frame().unwrap().is_some());
+
+        // a stream ending in the middle of a segment
+        let mut fake_stream = &stream[..1000];
+        let mut reader = crate::reader::VarReader::new(&mut fake_stream);
+        let mut frame = reader.next_frame().unwrap().unwrap();
+        frame.next_segment().unwrap().unwrap();
+        let mut segment = frame.next_segment().unwrap().unwrap();
+        let err = segment.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn large_lazy_segments() {
+        use std::io::Read;
+        use crate::{Error, FrameFormat, ReaderConfig};
+        use crate::config::{DEFAULT_MAX_FRAME_SIZE, DEFAULT_MAX_SEGMENT_SIZE};
+
+        // a segment past both default limits, generated as it is read rather than held in memory
+        let len = DEFAULT_MAX_FRAME_SIZE.max(DEFAULT_MAX_SEGMENT_SIZE) + 1;
+        let format = FrameFormat::default();
+        let mut header = Vec::new();
+        format.encode_frame_length(len + 4, &mut header).unwrap();
+        format.encode_segment_length(len, &mut header).unwrap();
+        let stream = || header.as_slice().chain(std::io::repeat(7).take(len as u64));
+
+        // collecting the frame is still bound by the frame and segment limits
+        let mut fake_stream = stream();
+        let mut reader = crate::reader::VarReader::new(&mut fake_stream);
+        assert!(matches!(reader.read_data_into(&mut crate::FrameBuf::new()), Err(Error::FrameTooLarge { .. })));
+
+        // streaming it is bound by the streaming limit alone
+        let mut fake_stream = stream();
+        let config = ReaderConfig { max_streamed_segment_size: DEFAULT_MAX_SEGMENT_SIZE, ..ReaderConfig::default() };
+        let mut reader = crate::reader::VarReader::with_config(&mut fake_stream, config);
+        let mut frame = reader.next_frame().unwrap().unwrap();
+        assert!(matches!(frame.next_segment(), Err(Error::SegmentTooLarge { max: DEFAULT_MAX_SEGMENT_SIZE, .. })));
+        drop(frame);
+
+        let mut fake_stream = stream();
+        let mut reader = crate::reader::VarReader::new(&mut fake_stream);
+        let mut frame = reader.next_frame().unwrap().unwrap();
+        let mut segment = frame.next_segment().unwrap().unwrap();
+        assert_eq!(segment.len(), len);
+        assert_eq!(segment.read_into(&mut std::io::sink()).unwrap(), len as u64);
+        drop(segment);
+        assert!(frame.next_segment().unwrap().is_none());
+        drop(frame);
+        assert!(reader.capacity() < len);
+        assert!(reader.next_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn reader_segments() {
+        use std::io::Cursor;
+
+        let large = vec![7u8; 100_000];
+        let mut writer = crate::writer::VarWriter::new();
+        writer.add_string("Hello, ");
+        writer.add_reader(Cursor::new(large.clone()), large.len());
+        // only the declared length is read from the source
+        writer.add_reader(Cursor::new(b"World!!!"), 6);
+        writer.add_reader(std::io::empty(), 0);
+        let mut stream: Vec<u8> = Vec::new();
+        writer.send(&mut stream).unwrap();
+
+        let mut fake_stream = stream.as_slice();
+        let mut reader = crate::reader::VarReader::new(&mut fake_stream);
+        let data = reader.read_data().unwrap().unwrap();
+        assert_eq!(data.len(), 4);
+        assert_eq!(data[0].to_string(), "Hello, ");
+        assert_eq!(data[1].as_ref(), large.as_slice());
+        assert_eq!(data[2].to_string(), "World!");
+        assert!(data[3].as_ref().is_empty());
+
+        // a source shorter than declared
+        let mut writer = crate::writer::VarWriter::new();
+        writer.add_string("Hello, ");
+        writer.add_reader(Cursor::new(b"World!"), 10);
+        match writer.send(&mut Vec::new()) {
+            Err(crate::Error::SourceTooShort { declared: 10, read: 6 }) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn writers_are_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<crate::writer::VarWriter>();
+        #[cfg(feature = "async-tokio")]
+        assert_send_sync::<crate::async_writer::VarWriter>();
+        #[cfg(feature = "futures-io")]
+        assert_send_sync::<crate::futures_writer::VarWriter>();
+    }
+
+    #[test]
+    fn file_segments() {
+        use std::io::Write;
+
+        let path = std::env::temp_dir().join(format!("send_it_file_segments_{}", std::process::id()));
+        let contents: Vec<u8> = (0..200_000u32).map(|i| i as u8).collect();
+        std::fs::File::create(&path).unwrap().write_all(&contents).unwrap();
+
+        let mut writer = crate::writer::VarWriter::new();
+        writer.add_string("Hello, ");
+        writer.add_file(std::fs::File::open(&path).unwrap(), 1000..150_000);
+        writer.add_string("World!");
+        // the file is read from the start of its range each time the frame is sent
+        let mut stream: Vec<u8> = Vec::new();
+        writer.send_without_clearing(&mut stream).unwrap();
+        writer.send(&mut stream).unwrap();
+
+        let mut fake_stream = stream.as_slice();
+        let mut reader = crate::reader::VarReader::new(&mut fake_stream);
+        for _ in 0..2 {
+            let data = reader.read_data().unwrap().unwrap();
+            assert_eq!(data[0].to_string(), "Hello, ");
+            assert_eq!(data[1].as_ref(), &contents[1000..150_000]);
+            assert_eq!(data[2].to_string(), "World!");
+        }
+
+        // a range past the end of the file
+        let mut writer = crate::writer::VarWriter::new();
+        writer.add_file(std::fs::File::open(&path).unwrap(), 199_000..201_000);
+        match writer.send(&mut Vec::new()) {
+            Err(crate::Error::SourceTooShort { declared: 2000, read: 1000 }) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // a reversed range is rejected before anything is written
+        let mut writer = crate::writer::VarWriter::new();
+        writer.add_string("Hello, ");
+        writer.add_file(std::fs::File::open(&path).unwrap(), std::ops::Range { start: 10, end: 5 });
+        let mut stream: Vec<u8> = Vec::new();
+        assert!(matches!(writer.send(&mut stream), Err(crate::Error::InvalidRange { start: 10, end: 5 })));
+        assert!(stream.is_empty());
+
+        // a socket, where linux copies the file without going through userspace
+        #[cfg(unix)]
+        {
+            let (mut client, mut server) = std::os::unix::net::UnixStream::pair().unwrap();
+            let file = std::fs::File::open(&path).unwrap();
+            let sender = std::thread::spawn(move || {
+                let mut writer = crate::writer::VarWriter::new();
+                writer.add_file(file, 0..200_000);
+                writer.send(&mut client).unwrap();
+            });
+            let mut reader = crate::reader::VarReader::new(&mut server);
+            let data = reader.read_data().unwrap().unwrap();
+            assert_eq!(data[0].as_ref(), contents.as_slice());
+            sender.join().unwrap();
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn segments_into_writers() {
+        // records the largest write, which is bounded by the reader's buffer
+        struct Sink(Vec<u8>, usize);
+        impl std::io::Write for Sink {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.extend_from_slice(buf);
+                self.1 = self.1.max(buf.len());
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let large = vec![7u8; 100_000];
+        let mut writer = crate::writer::VarWriter::new();
+        writer.add_string("Hello, ");
+        writer.add(crate::Segment::from(large.clone()));
+        writer.add_string("World!");
+        let mut stream: Vec<u8> = Vec::new();
+        writer.send_without_clearing(&mut stream).unwrap();
+        writer.send(&mut stream).unwrap();
+
+        let mut fake_stream = stream.as_slice();
+        let mut reader = crate::reader::VarReader::with_capacity(&mut fake_stream, 1024);
+        let mut sinks = [Sink(Vec::new(), 0), Sink(Vec::new(), 0), Sink(Vec::new(), 0)];
+        let count = reader.read_data_with(|index, segment| {
+            assert_eq!(segment.read_into(&mut sinks[index])?, segment.len() as u64);
+            Ok(())
+        }).unwrap();
+        assert_eq!(count, Some(3));
+        assert_eq!(sinks[0].0, b"Hello, ");
+        assert_eq!(sinks[1].0, large);
+        assert!(sinks[1].1 <= 1024);
+        assert_eq!(sinks[2].0, b"World!");
+
+        // a failing callback skips the rest of its frame
+        let err = reader.read_data_with(|_, _| Err(crate::Error::TruncatedFrame)).unwrap_err();
+        assert!(matches!(err, crate::Error::TruncatedFrame));
+        assert_eq!(reader.read_data_with(|_, _| Ok(())).unwrap(), None);
+
+        // a stream ending in the middle of a segment
+        let mut fake_stream = &stream[..1000];
+        let mut reader = crate::reader::VarReader::new(&mut fake_stream);
+        let err = reader.read_data_with(|_, segment| segment.read_into(&mut std::io::sink()).map(drop)).unwrap_err();
+        assert!(matches!(err, crate::Error::TruncatedFrame));
+    }
+
+    #[test]
+    fn frame_buf() {
+        let large = vec![7u8; 100_000];
+        let mut writer = crate::writer::VarWriter::new();
+        writer.add_string("Hello, ");
+        writer.add(crate::Segment::from(large.clone()));
+        writer.add_raw(&[]);
+        writer.add_string("World!");
+        let mut stream: Vec<u8> = Vec::new();
+        writer.send(&mut stream).unwrap();
+        // an empty frame
+        writer.send(&mut stream).unwrap();
+        writer.add_string("next");
+        writer.send(&mut stream).unwrap();
+
+        let mut fake_stream = stream.as_slice();
+        let mut reader = crate::reader::VarReader::with_capacity(&mut fake_stream, 1024);
+        let mut frame = crate::FrameBuf::new();
+        assert!(reader.read_data_into(&mut frame).unwrap());
+        assert_eq!(frame.len(), 4);
+        assert_eq!(frame.iter().collect::<Vec<_>>(), [&b"Hello, "[..], &large, &[], b"World!"]);
+        assert_eq!(frame.as_bytes().len(), 7 + large.len() + 6);
+        assert_eq!(frame.get(4), None);
+
+        // later frames reuse the buffer
+        let data = frame.as_bytes().as_ptr();
+        assert!(reader.read_data_into(&mut frame).unwrap());
+        assert!(frame.is_empty());
+        assert!(reader.read_data_into(&mut frame).unwrap());
+        assert_eq!(&frame[0], b"next");
+        assert_eq!(frame.as_bytes().as_ptr(), data);
+        assert!(!reader.read_data_into(&mut frame).unwrap());
+
+        // a stream ending in the middle of a segment
+        let mut fake_stream = &stream[..1000];
+        let mut reader = crate::reader::VarReader::new(&mut fake_stream);
+        let err = reader.read_data_into(&mut frame).unwrap_err();
+        assert!(matches!(err, crate::Error::TruncatedFrame));
+
+        // a stream that fails once in the middle of the large segment, like a read timeout
+        struct Flaky<'a> { data: &'a [u8], fail_at: usize }
+        impl std::io::Read for Flaky<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                if self.fail_at == 0 {
+                    self.fail_at = usize::MAX;
+                    return Err(std::io::ErrorKind::WouldBlock.into());
+                }
+                let len = buf.len().min(self.data.len()).min(self.fail_at);
+                buf[..len].copy_from_slice(&self.data[..len]);
+                self.data = &self.data[len..];
+                self.fail_at -= len;
+                Ok(len)
+            }
+        }
+        let mut flaky = Flaky { data: &stream, fail_at: 50_000 };
+        let mut reader = crate::reader::VarReader::with_capacity(&mut flaky, 1024);
+        let err = reader.read_data_into(&mut frame).unwrap_err();
+        assert!(matches!(err, crate::Error::Io(ref err) if err.kind() == std::io::ErrorKind::WouldBlock));
+        // the rest of the interrupted frame is skipped and the stream stays in step
+        assert!(reader.read_data_into(&mut frame).unwrap());
+        assert!(frame.is_empty());
+        assert!(reader.read_data_into(&mut frame).unwrap());
+        assert_eq!(&frame[0], b"next");
+        assert!(!reader.read_data_into(&mut frame).unwrap());
+    }
+
+    #[test]
+    fn borrowed_frame() {
+        let payload = vec![0xAB; 1000];
+        let mut frame = crate::writer::BorrowedFrame::new();
+        frame.add_str("Hello, ");
+        frame.add(payload.as_slice());
+        frame.add(b"World!".to_vec());
+        assert_eq!(frame.len(), 3);
+
+        // the same bytes as a VarWriter holding copies of the segments
+        let mut writer = crate::writer::VarWriter::new();
+        writer.add_string("Hello, ");
+        writer.add_raw(&payload);
+        writer.add_string("World!");
+        let mut expected = Vec::new();
+        writer.send(&mut expected).unwrap();
+        let mut stream = Vec::new();
+        frame.send(&mut stream).unwrap();
+        assert_eq!(stream, expected);
+
+        let mut fake_stream = stream.as_slice();
+        let mut reader = crate::reader::VarReader::new(&mut fake_stream);
+        let data = reader.read_data().unwrap().unwrap();
+        assert_eq!(data[1].as_ref(), payload.as_slice());
+
+        // a segment too long for the format is rejected before anything is written
+        let format = crate::FrameFormat { segment_length: crate::LengthPrefix::U8, ..crate::FrameFormat::default() };
+        frame.set_format(format);
+        let mut stream = Vec::new();
+        assert!(matches!(frame.send(&mut stream), Err(crate::Error::LengthOverflow { length: 1000, max: 255 })));
+        assert!(stream.is_empty());
+
+        frame.clear();
+        assert!(frame.is_empty());
+    }
+
+    #[test]
+    fn in_memory_frames() {
+        let segments = [crate::Segment::from("Hello, "), crate::Segment::new(), crate::Segment::from(vec![7u8; 300])];
+        let frame = crate::encode_frame(&segments);
+        assert_eq!(frame.len(), crate::encoded_len(&segments));
+
+        // the same bytes as a VarWriter sends
+        let mut writer = crate::writer::VarWriter::new();
+        for segment in &segments {
+            writer.add(segment.clone());
+        }
+        let mut stream: Vec<u8> = Vec::new();
+        writer.send(&mut stream).unwrap();
+        assert_eq!(stream, frame);
+
+        // frames are decoded one after the other from a buffer
+        let mut buffer = frame.clone();
+        buffer.extend_from_slice(&crate::encode_frame(&[crate::Segment::from("next")]));
+        let (decoded, consumed) = crate::decode_frame(&buffer).unwrap();
+        assert_eq!(consumed, frame.len());
+        assert_eq!(decoded.len(), 3);
+        assert_eq!(decoded[2].as_ref(), segments[2].as_ref());
+        let (decoded, consumed) = crate::decode_frame(&buffer[consumed..]).unwrap();
+        assert_eq!(decoded[0].to_string(), "next");
+        assert_eq!(consumed, 1 + 4 + 4);
+
+        // a frame without any segments is just its length prefix
+        let empty = crate::encode_frame(&[]);
+        assert_eq!(empty, [0]);
+        let (decoded, consumed) = crate::decode_frame(&empty).unwrap();
+        assert!(decoded.is_empty());
+        assert_eq!(consumed, 1);
+
+        // every prefix of a frame reports how much of it is missing
+        let header_len = frame.len() - (4 + 7 + 4 + 4 + 300);
+        for len in 0..frame.len() {
+            match crate::decode_frame(&frame[..len]) {
+                Err(crate::Error::Incomplete { needed }) if len < header_len => assert_eq!(needed, 1),
+                Err(crate::Error::Incomplete { needed }) => assert_eq!(needed, frame.len() - len),
+                other => panic!("unexpected result for {} bytes: {:?}", len, other),
+            }
+        }
+
+        // other formats and limits
+        let format = crate::FrameFormat { frame_length: crate::LengthPrefix::U16, ..crate::FrameFormat::default() };
+        let frame = format.encode_frame(&segments).unwrap();
+        assert_eq!(frame.len(), format.encoded_len(&segments).unwrap());
+        assert!(matches!(format.decode_frame(&frame[..1], crate::ReaderConfig::default()), Err(crate::Error::Incomplete { needed: 1 })));
+        let (decoded, _) = format.decode_frame(&frame, crate::ReaderConfig::default()).unwrap();
+        assert_eq!(decoded[0].to_string(), "Hello, ");
+        let config = crate::ReaderConfig { max_frame_size: 100, ..crate::ReaderConfig::default() };
+        assert!(matches!(format.decode_frame(&frame[..2], config), Err(crate::Error::FrameTooLarge { .. })));
+
+        let format = crate::FrameFormat { segment_length: crate::LengthPrefix::U8, ..crate::FrameFormat::default() };
+        assert!(matches!(format.encode_frame(&segments), Err(crate::Error::LengthOverflow { length: 300, max: 255 })));
+        assert!(matches!(format.encoded_len(&segments), Err(crate::Error::LengthOverflow { length: 300, max: 255 })));
+    }
+
+    #[test]
+    fn byte_order() {
+        use crate::ByteOrder;
+
+        let mut writer = crate::writer::VarWriter::with_byte_order(ByteOrder::BigEndian);
+        writer.add_string("Hello, ");
+        let mut stream: Vec<u8> = Vec::new();
+        writer.send(&mut stream).unwrap();
+        assert_eq!(stream[..5], [11, 0, 0, 0, 7]);
+
+        // a big-endian reader decodes the frame
+        let mut fake_stream = stream.as_slice();
+        let mut reader = crate::reader::VarReader::new(&mut fake_stream);
+        reader.set_byte_order(ByteOrder::BigEndian);
+        assert_eq!(reader.read_data().unwrap().unwrap()[0].to_string(), "Hello, ");
+
+        // while the default little-endian reader sees a 112 MiB segment
+        let mut fake_stream = stream.as_slice();
+        let mut reader = crate::reader::VarReader::new(&mut fake_stream);
+        assert!(matches!(reader.read_data(), Err(crate::Error::SegmentTooLarge { size: 0x0700_0000, .. })));
+    }
+
+    #[test]
+    fn frame_formats() {
+        use crate::{ByteOrder, FrameFormat, LengthPrefix};
+
+        let prefixes = [LengthPrefix::Varint, LengthPrefix::U8, LengthPrefix::U16, LengthPrefix::U32, LengthPrefix::U64];
+        for frame_length in prefixes {
+            for segment_length in prefixes {
+                for byte_order in [ByteOrder::LittleEndian, ByteOrder::BigEndian] {
+                    let format = FrameFormat { frame_length, segment_length, byte_order };
+                    let mut writer = crate::writer::VarWriter::with_format(format);
+                    writer.add_string("Hello, ");
+                    writer.add_raw(&[0xAB; 200]);
+                    writer.add_string("");
+                    let mut stream: Vec<u8> = Vec::new();
+                    writer.send(&mut stream).unwrap();
+
+                    let mut fake_stream = stream.as_slice();
+                    let mut reader = crate::reader::VarReader::new(&mut fake_stream);
+                    reader.set_format(format);
+                    let data = reader.read_data().unwrap().unwrap();
+                    assert_eq!(data.len(), 3, "{:?}", format);
+                    assert_eq!(data[0].to_string(), "Hello, ");
+                    assert_eq!(data[1].as_ref(), &[0xAB; 200]);
+                    assert!(data[2].as_ref().is_empty());
+                    assert!(reader.read_data().unwrap().is_none());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn length_overflow() {
+        use crate::{Error, FrameFormat, LengthPrefix};
+
+        // a segment too long for a u8 length is rejected before anything is written
+        let format = FrameFormat { segment_length: LengthPrefix::U8, ..FrameFormat::default() };
+        let mut writer = crate::writer::VarWriter::with_format(format);
+        writer.add_string("Hello, ");
+        writer.add_raw(&[0; 256]);
+        let mut stream: Vec<u8> = Vec::new();
+        assert!(matches!(writer.send(&mut stream), Err(Error::LengthOverflow { length: 256, max: 255 })));
+        assert!(stream.is_empty());
+
+        // as is a frame too long for a u16 total size
+        let format = FrameFormat { frame_length: LengthPrefix::U16, ..FrameFormat::default() };
+        let mut writer = crate::writer::VarWriter::with_format(format);
+        writer.add_raw(&[0; 65_532]);
+        assert!(matches!(writer.send(&mut stream), Err(Error::LengthOverflow { length: 65_536, max: 65_535 })));
+        assert!(stream.is_empty());
+    }
+
+    #[test]
+    fn single_write_per_frame() {
+        use std::io::{IoSlice, Write};
+
+        // a stream recording every write call made on it
+        #[derive(Default)]
+        struct RecordingStream {
+            calls: usize,
+            data: Vec<u8>,
+        }
+
+        impl Write for RecordingStream {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.calls += 1;
+                self.data.extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> std::io::Result<usize> {
+                self.calls += 1;
+                bufs.iter().for_each(|buf| self.data.extend_from_slice(buf));
+                Ok(bufs.iter().map(|buf| buf.len()).sum())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut writer = crate::writer::VarWriter::new();
+        for i in 0..100u8 {
+            writer.add_raw(&[i; 3]);
+        }
+        writer.add_string("");
+        let mut stream = RecordingStream::default();
+        writer.send(&mut stream).unwrap();
+        assert_eq!(stream.calls, 1);
+
+        let mut fake_stream = stream.data.as_slice();
+        let mut reader = crate::reader::VarReader::new(&mut fake_stream);
+        let data = reader.read_data().unwrap().unwrap();
+        assert_eq!(data.len(), 101);
+        assert_eq!(data[99].as_ref(), &[99; 3]);
+        assert!(reader.read_data().unwrap().is_none());
+    }
+
+    #[test]
+    fn reader_buffering() {
+        use std::io::Read;
+
+        // a stream recording the size of every read made on it
+        struct RecordingStream<'a> {
+            data: &'a [u8],
+            reads: Vec<usize>,
+        }
+
+        impl Read for RecordingStream<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                self.reads.push(buf.len());
+                self.data.read(buf)
+            }
+        }
+
+        let mut writer = crate::writer::VarWriter::new();
+        writer.add_string("Hello, ");
+        writer.add_string("World!");
+        writer.add_raw(&[7; 1000]);
+        let mut stream: Vec<u8> = Vec::new();
+        writer.send_without_clearing(&mut stream).unwrap();
+        writer.send(&mut stream).unwrap();
+
+        // both frames are served from a single read
+        let mut recording = RecordingStream { data: &stream, reads: Vec::new() };
+        let mut reader = crate::reader::VarReader::new(&mut recording);
+        assert_eq!(reader.read_data().unwrap().unwrap()[1].to_string(), "World!");
+        assert_eq!(reader.read_data().unwrap().unwrap()[2].as_ref(), &[7; 1000]);
+        assert!(reader.read_data().unwrap().is_none());
+        assert_eq!(recording.reads.len(), 2);
+
+        // with a small buffer the large segment is read directly into the segment
+        let mut recording = RecordingStream { data: &stream, reads: Vec::new() };
+        let mut reader = crate::reader::VarReader::with_capacity(&mut recording, 16);
+        assert_eq!(reader.read_data().unwrap().unwrap()[2].as_ref(), &[7; 1000]);
+        assert!(reader.buffer().is_empty());
+        assert!(recording.reads.iter().any(|&len| len > 16));
+
+        // the bytes read past the end of a frame are handed back when the reader is unwrapped
+        let mut fake_stream = stream.as_slice();
+        let mut reader = crate::reader::VarReader::new(&mut fake_stream);
+        assert_eq!(reader.read_data().unwrap().unwrap().len(), 3);
+        let (_, leftover) = reader.into_parts();
+        assert_eq!(leftover, stream[stream.len() / 2..]);
+    }
+
+    #[test]
+    fn four_gib_segments() {
+        use crate::{Error, FrameFormat, LengthPrefix, ReaderConfig};
+
+        // segment lengths are checked against the prefix without materialising 4 GiB of data
+        let max = u32::MAX as usize;
+        let format = FrameFormat::default();
+        assert_eq!(format.segment_size(max).unwrap(), max + 4);
+        assert!(matches!(format.segment_size(max + 1), Err(Error::LengthOverflow { length: 0x1_0000_0000, max: 0xFFFF_FFFF })));
+
+        let format = FrameFormat { segment_length: LengthPrefix::U64, ..FrameFormat::default() };
+        assert_eq!(format.segment_size(max + 1).unwrap(), max + 9);
+
+        // sizes that do not fit in memory are rejected rather than overflowing
+        assert!(matches!(format.segment_size(usize::MAX), Err(Error::LengthOverflow { .. })));
+        let mut writer = crate::writer::VarWriter::with_format(format);
+        writer.add_reader(std::io::empty(), usize::MAX);
+        let mut stream = Vec::new();
+        assert!(matches!(writer.send(&mut stream), Err(Error::LengthOverflow { .. })));
+        assert!(stream.is_empty());
+
+        // a u64 segment length past 4 GiB survives the round trip through the reader
+        let mut stream = Vec::new();
+        format.encode_frame_length(max + 9, &mut stream).unwrap();
+        format.encode_segment_length(max + 1, &mut stream).unwrap();
+        assert_eq!(stream, [0x88, 0x80, 0x80, 0x80, 0x10, 0, 0, 0, 0, 1, 0, 0, 0]);
+
+        let mut fake_stream = stream.as_slice();
+        let config = ReaderConfig { max_segment_size: max, ..ReaderConfig::unlimited() };
+        let mut reader = crate::reader::VarReader::with_config(&mut fake_stream, config);
+        reader.set_format(format);
+        assert!(matches!(reader.read_data(), Err(Error::SegmentTooLarge { size: 0x1_0000_0000, .. })));
+    }
+
+    #[test]
+    fn framed_stream() {
+        use std::net::{TcpListener, TcpStream};
+        use crate::framed::FramedStream;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+
+        // the server's FramedStream owns its TcpStream, so it can be moved into a thread
+        let mut server = FramedStream::new(server);
+        let echo = std::thread::spawn(move || {
+            while let Some(data) = server.read_data().unwrap() {
+                server.send(&data).unwrap();
+            }
+        });
+
+        let mut client = FramedStream::new(client);
+        for i in 0..10u8 {
+            client.send(&[crate::Segment::from("Hello, "), crate::Segment::from(vec![i; 100])]).unwrap();
+            let data = client.read_data().unwrap().unwrap();
+            assert_eq!(data[0].to_string(), "Hello, ");
+            assert_eq!(data[1].as_ref(), &[i; 100]);
+        }
+        client.get_ref().shutdown(std::net::Shutdown::Write).unwrap();
+        assert!(client.read_data().unwrap().is_none());
+        echo.join().unwrap();
+    }
+
+    #[cfg(feature = "async-tokio")]
+    #[tokio::test]
+    async fn async_framed_stream() {
+        use crate::async_framed::FramedStream;
+
+        let (client, server) = tokio::io::duplex(64);
+
+        // the server's FramedStream owns its stream, so it can be moved into a spawned task
+        let mut server = FramedStream::new(server);
+        let echo = tokio::spawn(async move {
+            while let Some(data) = server.read_data().await.unwrap() {
+                server.send(&data).await.unwrap();
+            }
+        });
+
+        let mut client = FramedStream::new(client);
+        for i in 0..10u8 {
+            client.send(&[crate::Segment::from("Hello, "), crate::Segment::from(vec![i; 100])]).await.unwrap();
+            let data = client.read_data().await.unwrap().unwrap();
+            assert_eq!(data[0].to_string(), "Hello, ");
+            assert_eq!(data[1].as_ref(), &[i; 100]);
+        }
+        drop(client);
+        echo.await.unwrap();
+    }
+
+    #[cfg(feature = "async-tokio")]
+    #[tokio::test]
+    async fn sync_and_async_together() {
+        // write with the blocking writer and read with the async reader
+        let mut writer = crate::writer::VarWriter::new();
+        writer.add_string("Hello, ");
+        writer.add_string("World!");
+        let mut stream: Vec<u8> = Vec::new();
+        writer.send(&mut stream).unwrap();
+
+        let mut fake_stream = stream.as_slice();
+        let mut reader = crate::async_reader::VarReader::new(&mut fake_stream);
+        let data = reader.read_data().await.unwrap().unwrap();
+        assert_eq!(crate::Segment::to_readable(data), vec!["Hello, ", "World!"]);
+
+        // write with the async writer and read with the blocking reader
+        let mut writer = crate::async_writer::VarWriter::new();
+        writer.add_string("Hello, ");
+        writer.add_string("World!");
+        let mut async_stream: Vec<u8> = Vec::new();
+        writer.send(&mut async_stream).await.unwrap();
+        assert_eq!(async_stream, stream);
+
+        let mut fake_stream = async_stream.as_slice();
+        let mut reader = crate::reader::VarReader::new(&mut fake_stream);
+        let data = reader.read_data().unwrap().unwrap();
+        assert_eq!(crate::Segment::to_readable(data), vec!["Hello, ", "World!"]);
+    }
+
+    #[cfg(feature = "async-tokio")]
+    #[tokio::test]
+    async fn async_reader_eof() {
+        use crate::Error;
+
+        // a stream ending right after a frame ends cleanly
+        let stream: Vec<u8> = vec![10, 6, 0, 0, 0, 87, 111, 114, 108, 100, 33];
+        let mut fake_stream = stream.as_slice();
+        let mut reader = crate::async_reader::VarReader::new(&mut fake_stream);
+        assert_eq!(reader.read_data().await.unwrap().unwrap()[0].to_string(), "World!");
+        assert!(reader.read_data().await.unwrap().is_none());
+        // reading again at EOF keeps reporting the end of the stream instead of empty frames
+        assert!(reader.read_data().await.unwrap().is_none());
+
+        // a stream ending inside the varint, the segment length or the payload is truncated
+        let stream: Vec<u8> = vec![10, 6, 0, 0, 0, 87, 111, 114, 108, 100, 33];
+        for mut fake_stream in [&[0x8A][..], &stream[..3], &stream[..8]] {
+            let mut reader = crate::async_reader::VarReader::new(&mut fake_stream);
+            assert!(matches!(reader.read_data().await, Err(Error::TruncatedFrame)));
+        }
+    }
+
+    #[cfg(feature = "async-tokio")]
+    #[tokio::test]
+    async fn async_read_cancel_safety() {
+        use std::time::Duration;
+        use tokio::io::AsyncWriteExt;
+
+        // a small segment and one larger than the read buffer, which is read directly into the segment
+        let mut writer = crate::writer::VarWriter::new();
+        writer.add_string("Hello, ");
+        writer.add(crate::Segment::from(vec![7u8; 20_000]));
+        let mut frame: Vec<u8> = Vec::new();
+        writer.send(&mut frame).unwrap();
+
+        const FRAMES: usize = 5;
+        let (mut client, mut server) = tokio::io::duplex(64);
+        let sender = tokio::spawn(async move {
+            for _ in 0..FRAMES {
+                for chunk in frame.chunks(997) {
+                    client.write_all(chunk).await.unwrap();
+                    tokio::time::sleep(Duration::from_micros(200)).await;
+                }
+            }
+        });
+
+        // keep abandoning reads halfway through a frame, no bytes may be lost
+        let mut reader = crate::async_reader::VarReader::new(&mut server);
+        let mut received = 0;
+        let mut timeouts = 0;
+        while received < FRAMES {
+            tokio::select! {
+                data = reader.read_data() => {
+                    let data = data.unwrap().unwrap();
+                    assert_eq!(data[0].to_string(), "Hello, ");
+                    assert_eq!(data[1].as_ref(), vec![7u8; 20_000].as_slice());
+                    received += 1;
+                }
+                _ = tokio::time::sleep(Duration::from_micros(100)) => timeouts += 1,
+            }
+        }
+        assert!(timeouts > 0);
+
+        sender.await.unwrap();
+        assert!(reader.read_data().await.unwrap().is_none());
+    }
+
+    #[cfg(feature = "codec")]
+    #[tokio::test]
+    async fn codec() {
+        use futures::{SinkExt, StreamExt};
+        use tokio_util::codec::{Framed, FramedRead};
+        use crate::codec::SendItCodec;
+        use crate::{Error, ReaderConfig, Segment};
+
+        let (client, server) = tokio::io::duplex(64);
+        let mut client = Framed::new(client, SendItCodec::new());
+        let mut server = Framed::new(server, SendItCodec::new());
+
+        let frame = vec![Segment::from("Hello, "), Segment::from(vec![7u8; 1000]), Segment::from("World!")];
+        let sender = tokio::spawn(async move {
+            client.send(frame.clone()).await.unwrap();
+            client.send(Vec::new()).await.unwrap();
+            client.send(frame).await.unwrap();
+        });
+        for expected_len in [3, 0, 3] {
+            let data = server.next().await.unwrap().unwrap();
+            assert_eq!(data.len(), expected_len);
+        }
+        sender.await.unwrap();
+        // the client was dropped at a frame boundary
+        assert!(server.next().await.is_none());
+
+        // a stream ending mid-frame is truncated
+        let stream: Vec<u8> = vec![10, 6, 0, 0, 0, 87, 111];
+        let mut reader = FramedRead::new(stream.as_slice(), SendItCodec::new());
+        assert!(matches!(reader.next().await, Some(Err(Error::TruncatedFrame))));
+
+        // frames over the limits are neither sent nor accepted
+        let config = ReaderConfig { max_segment_size: 4, ..ReaderConfig::default() };
+        let mut sink = tokio_util::codec::FramedWrite::new(Vec::new(), SendItCodec::with_config(config));
+        let err = sink.send(vec![Segment::from("Hello, ")]).await.unwrap_err();
+        assert!(matches!(err, Error::SegmentTooLarge { size: 7, max: 4 }));
+        assert!(sink.get_ref().is_empty());
+
+        let stream: Vec<u8> = vec![11, 7, 0, 0, 0, 72, 101, 108, 108, 111, 44, 32];
+        let mut reader = FramedRead::new(stream.as_slice(), SendItCodec::with_config(config));
+        assert!(matches!(reader.next().await, Some(Err(Error::SegmentTooLarge { size: 7, max: 4 }))));
+    }
+
+    #[cfg(feature = "futures-io")]
+    #[test]
+    fn futures_io() {
+        use crate::{Error, Segment};
+
+        futures::executor::block_on(async {
+            let mut writer = crate::futures_writer::VarWriter::new();
+            writer.add_string("Hello, ");
+            writer.add_string("World!");
+            let mut stream: Vec<u8> = Vec::new();
+            writer.send_without_clearing(&mut stream).await.unwrap();
+            writer.send(&mut stream).await.unwrap();
+
+            // the futures-io and blocking writers produce the same bytes
+            let mut blocking = crate::writer::VarWriter::new();
+            blocking.add_string("Hello, ");
+            blocking.add_string("World!");
+            let mut expected: Vec<u8> = Vec::new();
+            blocking.send_without_clearing(&mut expected).unwrap();
+            blocking.send(&mut expected).unwrap();
+            assert_eq!(stream, expected);
+
+            // segments read from a source are copied through in chunks
+            let large = vec![7u8; 100_000];
+            let mut writer = crate::futures_writer::VarWriter::new();
+            writer.add_reader(futures::io::Cursor::new(large.clone()), large.len());
+            let mut sourced: Vec<u8> = Vec::new();
+            writer.send(&mut sourced).await.unwrap();
+            let mut fake_stream = sourced.as_slice();
+            let mut reader = crate::futures_reader::VarReader::new(&mut fake_stream);
+            assert_eq!(reader.read_data().await.unwrap().unwrap()[0].as_ref(), large.as_slice());
+
+            let mut writer = crate::futures_writer::VarWriter::new();
+            writer.add_reader(&b"Hello"[..], 7);
+            assert!(matches!(writer.send(&mut Vec::new()).await, Err(Error::SourceTooShort { declared: 7, read: 5 })));
+
+            // a tiny buffer makes every header span several reads
+            let mut fake_stream = stream.as_slice();
+            let mut reader = crate::futures_reader::VarReader::with_capacity(&mut fake_stream, 3);
+            for _ in 0..2 {
+                let data = reader.read_data().await.unwrap().unwrap();
+                assert_eq!(data[0].to_string(), "Hello, ");
+                assert_eq!(data[1].to_string(), "World!");
+            }
+            assert!(reader.read_data().await.unwrap().is_none());
+
+            let mut fake_stream = &stream[..8];
+            let mut reader = crate::futures_reader::VarReader::new(&mut fake_stream);
+            assert!(matches!(reader.read_data().await, Err(Error::TruncatedFrame)));
+
+            // a segment larger than the buffer is read straight into the segment
+            let mut framed = crate::futures_framed::FramedStream::new(futures::io::Cursor::new(Vec::new()));
+            framed.send(&[Segment::from(vec![7u8; 20_000])]).await.unwrap();
+            framed.get_mut().set_position(0);
+            let data = framed.read_data().await.unwrap().unwrap();
+            assert_eq!(data[0].as_ref(), vec![7u8; 20_000].as_slice());
+            assert!(framed.read_data().await.unwrap().is_none());
+        });
+    }
+
+    #[cfg(feature = "async-tokio")]
+    #[tokio::test]
+    async fn stream_and_sink() {
+        use futures::{SinkExt, StreamExt};
+        use crate::async_writer::FrameSink;
+        use crate::{Error, Segment};
+
+        // a small pipe makes the sink wait for the reader between frames
+        let (client, mut server) = tokio::io::duplex(64);
+        let frames: Vec<Vec<Segment>> = (0..20)
+            .map(|i| vec![Segment::from(format!("frame {i}")), Segment::from(vec![i as u8; 100])])
+            .collect();
+        let expected = frames.clone();
+        let sender = tokio::spawn(async move {
+            let mut sink = FrameSink::new(client);
+            futures::stream::iter(frames.into_iter().map(Ok)).forward(&mut sink).await.unwrap();
+            // dropping the sink closes the pipe
+        });
+
+        let reader = crate::async_reader::VarReader::new(&mut server);
+        let received: Vec<Vec<Segment>> = reader.into_stream().map(Result::unwrap).collect().await;
+        sender.await.unwrap();
+        assert_eq!(received.len(), expected.len());
+        for (frame, expected) in received.iter().zip(&expected) {
+            assert_eq!(frame[0].to_string(), expected[0].to_string());
+            assert_eq!(frame[1].as_ref(), expected[1].as_ref());
+        }
+
+        // a truncated frame is reported as an error item
+        let stream: Vec<u8> = vec![10, 6, 0, 0, 0, 87, 111];
+        let mut fake_stream = stream.as_slice();
+        let mut frames = crate::async_reader::VarReader::new(&mut fake_stream).into_stream();
+        assert!(matches!(frames.next().await, Some(Err(Error::TruncatedFrame))));
+        assert!(frames.next().await.is_none());
+        assert!(futures::stream::FusedStream::is_terminated(&frames));
+
+        // a frame that can't be encoded is rejected by start_send without writing anything
+        let format = crate::FrameFormat { segment_length: crate::LengthPrefix::U8, ..crate::FrameFormat::default() };
+        let mut sink = FrameSink::with_format(Vec::new(), format);
+        let err = sink.send(vec![Segment::from(vec![0u8; 300])]).await.unwrap_err();
+        assert!(matches!(err, Error::LengthOverflow { length: 300, max: 255 }));
+        assert!(sink.get_ref().is_empty());
+
+        // a second frame started before the first is written is refused instead of replacing it
+        let mut sink = FrameSink::new(Vec::new());
+        futures::Sink::start_send(std::pin::Pin::new(&mut sink), vec![Segment::from("first")]).unwrap();
+        assert!(matches!(futures::Sink::start_send(std::pin::Pin::new(&mut sink), vec![Segment::from("second")]), Err(Error::Io(_))));
+        sink.flush().await.unwrap();
+        assert_eq!(sink.get_ref().as_slice(), crate::encode_frame(&[Segment::from("first")]));
+    }
+
+    #[cfg(feature = "async-tokio")]
+    #[tokio::test]
+    async fn async_lazy_segments() {
+        use tokio::io::AsyncReadExt;
+
+        let large = vec![7u8; 20_000];
+        let mut writer = crate::writer::VarWriter::new();
+        writer.add_string("Hello, ");
+        writer.add(crate::Segment::from(large.clone()));
+        writer.add_string("World!");
+        let mut stream: Vec<u8> = Vec::new();
+        writer.send(&mut stream).unwrap();
+        writer.add_string("next");
+        writer.send(&mut stream).unwrap();
+
+        let mut fake_stream = stream.as_slice();
+        let mut reader = crate::async_reader::VarReader::with_capacity(&mut fake_stream, 64);
+        {
+            let mut frame = reader.next_frame().await.unwrap().unwrap();
+            let mut segment = frame.next_segment().await.unwrap().unwrap();
+            let mut hello = String::new();
+            segment.read_to_string(&mut hello).await.unwrap();
+            assert_eq!(hello, "Hello, ");
+
+            let mut segment = frame.next_segment().await.unwrap().unwrap();
+            let mut start = [0; 100];
+            segment.read_exact(&mut start).await.unwrap();
+            assert_eq!(start, large[..100]);
+            // the rest of the large segment is skipped
+            let mut segment = frame.next_segment().await.unwrap().unwrap();
+            let mut world = String::new();
+            segment.read_to_string(&mut world).await.unwrap();
+            assert_eq!(world, "World!");
+        }
+        // a frame dropped before its end is skipped
+        reader.next_frame().await.unwrap().unwrap();
+        assert!(reader.read_data().await.unwrap().is_none());
+
+        let mut fake_stream = stream.as_slice();
+        let mut reader = crate::async_reader::VarReader::new(&mut fake_stream);
+        reader.next_frame().await.unwrap().unwrap();
+        assert_eq!(reader.read_data().await.unwrap().unwrap()[0].to_string(), "next");
+    }
+
+    #[cfg(feature = "async-tokio")]
+    #[tokio::test]
+    async fn async_reader_segments() {
+        let large = vec![7u8; 100_000];
+        let mut writer = crate::async_writer::VarWriter::new();
+        writer.add_string("Hello, ");
+        writer.add_reader(std::io::Cursor::new(large.clone()), large.len());
+        writer.add_string("World!");
+        let mut stream: Vec<u8> = Vec::new();
+        writer.send(&mut stream).await.unwrap();
+
+        let mut fake_stream = stream.as_slice();
+        let mut reader = crate::async_reader::VarReader::new(&mut fake_stream);
+        let data = reader.read_data().await.unwrap().unwrap();
+        assert_eq!(data[1].as_ref(), large.as_slice());
+        assert_eq!(data[2].to_string(), "World!");
+
+        let mut writer = crate::async_writer::VarWriter::new();
+        writer.add_reader(&b"Hello"[..], 7);
+        match writer.send(&mut Vec::new()).await {
+            Err(crate::Error::SourceTooShort { declared: 7, read: 5 }) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[cfg(feature = "async-tokio")]
+    #[tokio::test]
+    async fn async_segments_into_writers() {
+        let large = vec![7u8; 100_000];
+        let mut writer = crate::async_writer::VarWriter::new();
+        writer.add_string("Hello, ");
+        writer.add(crate::Segment::from(large.clone()));
+        let mut stream: Vec<u8> = Vec::new();
+        writer.send(&mut stream).await.unwrap();
+
+        let mut fake_stream = stream.as_slice();
+        let mut reader = crate::async_reader::VarReader::with_capacity(&mut fake_stream, 1024);
+        let mut frame = reader.next_frame().await.unwrap().unwrap();
+        let mut files = Vec::new();
+        while let Some(mut segment) = frame.next_segment().await.unwrap() {
+            let mut file = Vec::new();
+            segment.read_into(&mut file).await.unwrap();
+            files.push(file);
+        }
+        assert_eq!(files, [b"Hello, ".to_vec(), large]);
+    }
+
+    #[cfg(feature = "bytes")]
+    #[test]
+    fn shared_segments() {
+        let data = b"Hello, World!".to_vec();
+        let ptr = data.as_ptr();
+
+        // conversions to and from Vec and Bytes keep the same allocation
+        let segment = crate::Segment::from(data);
+        let clone = segment.clone();
+        assert_eq!(clone.as_ref().as_ptr(), ptr);
+        let world = segment.slice(7..);
+        assert_eq!(world.to_string(), "World!");
+        assert_eq!(world.as_ref().as_ptr(), ptr.wrapping_add(7));
+        let bytes = bytes::Bytes::from(clone);
+        assert_eq!(bytes.as_ptr(), ptr);
+        assert_eq!(crate::Segment::from(bytes).as_ref().as_ptr(), ptr);
+        drop(world);
+        let data = Vec::from(segment);
+        assert_eq!(data.as_ptr(), ptr);
+
+        let mut segment = crate::Segment::from("Hello, ");
+        segment.append(crate::Segment::from("World!"));
+        assert_eq!(segment.to_string(), "Hello, World!");
+
+        // the segments of a received frame share its buffer
+        let mut writer = crate::writer::VarWriter::new();
+        writer.add_string("Hello, ");
+        writer.add_string("World!");
+        let mut stream: Vec<u8> = Vec::new();
+        writer.send(&mut stream).unwrap();
+        let mut fake_stream = stream.as_slice();
+        let mut reader = crate::reader::VarReader::new(&mut fake_stream);
+        let mut frame = crate::FrameBuf::new();
+        assert!(reader.read_data_into(&mut frame).unwrap());
+        let ptr = frame.as_bytes().as_ptr();
+        let segments = frame.into_segments();
+        assert_eq!(segments[0].to_string(), "Hello, ");
+        assert_eq!(segments[0].as_ref().as_ptr(), ptr);
+        assert_eq!(segments[1].as_ref().as_ptr(), ptr.wrapping_add(7));
+    }
+}
@@ -0,0 +1,54 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Reads from a stream into a slice, adapting the read trait of an async runtime
+pub(crate) type PollRead<R> = fn(Pin<&mut R>, &mut Context<'_>, &mut [u8]) -> Poll<io::Result<usize>>;
+
+/// Writes slices to a stream, adapting the vectored write trait of an async runtime
+#[cfg(feature="writing")]
+pub(crate) type PollWrite<W> = fn(Pin<&mut W>, &mut Context<'_>, &[io::IoSlice<'_>]) -> Poll<io::Result<usize>>;
+
+/// Adapters for tokio streams
+#[cfg(feature="async-tokio")]
+pub(crate) mod tokio_io {
+    use std::io;
+    use std::pin::Pin;
+    use std::task::{ready, Context, Poll};
+
+    use tokio::io::{AsyncRead, ReadBuf};
+
+    /// Reads from a tokio stream into a plain slice
+    pub(crate) fn poll_read<R: AsyncRead + ?Sized>(reader: Pin<&mut R>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let mut buf = ReadBuf::new(buf);
+        ready!(reader.poll_read(cx, &mut buf))?;
+        Poll::Ready(Ok(buf.filled().len()))
+    }
+
+    /// Writes slices to a tokio stream
+    #[cfg(feature="writing")]
+    pub(crate) fn poll_write_vectored<W: tokio::io::AsyncWrite>(writer: Pin<&mut W>, cx: &mut Context<'_>, slices: &[io::IoSlice<'_>]) -> Poll<io::Result<usize>> {
+        writer.poll_write_vectored(cx, slices)
+    }
+}
+
+/// Adapters for futures-io streams
+#[cfg(feature="futures-io")]
+pub(crate) mod futures_io {
+    use std::io;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    use futures_util::io::AsyncRead;
+
+    /// Reads from a futures-io stream
+    pub(crate) fn poll_read<R: AsyncRead + ?Sized>(reader: Pin<&mut R>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        reader.poll_read(cx, buf)
+    }
+
+    /// Writes slices to a futures-io stream
+    #[cfg(feature="writing")]
+    pub(crate) fn poll_write_vectored<W: futures_util::io::AsyncWrite>(writer: Pin<&mut W>, cx: &mut Context<'_>, slices: &[io::IoSlice<'_>]) -> Poll<io::Result<usize>> {
+        writer.poll_write_vectored(cx, slices)
+    }
+}
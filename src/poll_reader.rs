@@ -0,0 +1,421 @@
+use std::io::ErrorKind;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use crate::decoder::{FrameDecoder, SegmentStart};
+use crate::poll_io::PollRead;
+use crate::{Error, Result, Segment};
+
+/// The buffered decoding state shared by the readers that wrap an async stream, whether tokio or futures-io.
+/// * All parsing is done by the [`FrameDecoder`], this only moves bytes from the stream into it.
+pub(crate) struct ReaderCore<R> {
+    pub(crate) reader: R,
+    pub(crate) decoder: FrameDecoder,
+    capacity: usize,
+    /// Whether a frame is being read one segment at a time
+    lazy: bool,
+}
+
+impl<R> ReaderCore<R> {
+    pub(crate) fn new(reader: R, capacity: usize) -> Self {
+        ReaderCore { reader, decoder: FrameDecoder::new(), capacity: capacity.max(1), lazy: false }
+    }
+
+    pub(crate) fn buffer(&self) -> &[u8] {
+        self.decoder.buffer()
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub(crate) fn into_parts(self) -> (R, Vec<u8>) {
+        let leftover = self.buffer().to_vec();
+        (self.reader, leftover)
+    }
+}
+
+impl<R: Unpin> ReaderCore<R> {
+    /// Reads until a frame is complete, keeping all progress in the decoder so that
+    /// dropping the future between polls never loses bytes.
+    pub(crate) fn poll_read_data(&mut self, cx: &mut Context<'_>, poll_read: PollRead<R>) -> Poll<Result<Option<Vec<Segment>>>> {
+        ready!(self.poll_skip_frame(cx, poll_read))?;
+        loop {
+            if let Some(frame) = self.decoder.decode()? {
+                return Poll::Ready(Ok(Some(frame)));
+            }
+            let read = match self.decoder.pending_segment() {
+                // large segments bypass the buffer, growing with the data received so far
+                Some((segment, remaining)) if remaining >= self.capacity => {
+                    let start = segment.len();
+                    segment.resize(start + remaining.min(start.max(self.capacity)), 0);
+                    let result = poll_read(Pin::new(&mut self.reader), cx, &mut segment[start..]);
+                    let read = match result {
+                        Poll::Ready(Ok(read)) => read,
+                        _ => 0,
+                    };
+                    segment.truncate(start + read);
+                    match ready!(result) {
+                        Ok(read) => read,
+                        Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                        Err(err) => return Poll::Ready(Err(err.into())),
+                    }
+                }
+                _ => ready!(self.poll_fill(cx, poll_read))?,
+            };
+            if read == 0 {
+                self.decoder.finish()?;
+                return Poll::Ready(Ok(None));
+            }
+        }
+    }
+
+    /// Reads more of the stream into the decoder's buffer, returning the number of bytes read
+    fn poll_fill(&mut self, cx: &mut Context<'_>, poll_read: PollRead<R>) -> Poll<Result<usize>> {
+        loop {
+            let buf = self.decoder.read_buf(self.capacity);
+            let len = buf.len().min(self.capacity);
+            match ready!(poll_read(Pin::new(&mut self.reader), cx, &mut buf[..len])) {
+                Ok(read) => {
+                    self.decoder.commit(read);
+                    return Poll::Ready(Ok(read));
+                }
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Poll::Ready(Err(err.into())),
+            }
+        }
+    }
+
+    /// Reads the length of the next frame so that its segments can be read one at a time,
+    /// returning false if the stream ended cleanly before it
+    pub(crate) fn poll_start_frame(&mut self, cx: &mut Context<'_>, poll_read: PollRead<R>) -> Poll<Result<bool>> {
+        ready!(self.poll_skip_frame(cx, poll_read))?;
+        loop {
+            // the frame is only read one segment at a time, so it is never held in memory
+            if self.decoder.decode_frame_start(true)? {
+                self.lazy = true;
+                return Poll::Ready(Ok(true));
+            }
+            if ready!(self.poll_fill(cx, poll_read))? == 0 {
+                self.decoder.finish()?;
+                return Poll::Ready(Ok(false));
+            }
+        }
+    }
+
+    /// Reads the length of the next segment of a frame started with `poll_start_frame`,
+    /// skipping whatever is left of the previous one
+    pub(crate) fn poll_next_segment(&mut self, cx: &mut Context<'_>, poll_read: PollRead<R>) -> Poll<Result<Option<usize>>> {
+        ready!(self.poll_skip_segment(cx, poll_read))?;
+        loop {
+            match self.decoder.decode_segment_start()? {
+                Some(SegmentStart::Segment(size)) => return Poll::Ready(Ok(Some(size))),
+                Some(SegmentStart::FrameEnd) => {
+                    self.lazy = false;
+                    return Poll::Ready(Ok(None));
+                }
+                None if ready!(self.poll_fill(cx, poll_read))? == 0 => return Poll::Ready(Err(Error::TruncatedFrame)),
+                None => {}
+            }
+        }
+    }
+
+    /// Reads the data of the current segment into `buf`, returning 0 once all of it has been read
+    pub(crate) fn poll_read_segment(&mut self, cx: &mut Context<'_>, poll_read: PollRead<R>, buf: &mut [u8]) -> Poll<Result<usize>> {
+        let max = buf.len().min(self.decoder.segment_remaining());
+        if max == 0 {
+            return Poll::Ready(Ok(0));
+        }
+        if self.decoder.buffer().is_empty() {
+            if max >= self.capacity {
+                // large reads bypass the buffer
+                loop {
+                    match ready!(poll_read(Pin::new(&mut self.reader), cx, &mut buf[..max])) {
+                        Ok(0) => return Poll::Ready(Err(Error::TruncatedFrame)),
+                        Ok(read) => {
+                            self.decoder.consume_segment_data(read);
+                            return Poll::Ready(Ok(read));
+                        }
+                        Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                        Err(err) => return Poll::Ready(Err(err.into())),
+                    }
+                }
+            }
+            if ready!(self.poll_fill(cx, poll_read))? == 0 {
+                return Poll::Ready(Err(Error::TruncatedFrame));
+            }
+        }
+        let data = self.decoder.take_segment_data(max);
+        buf[..data.len()].copy_from_slice(data);
+        Poll::Ready(Ok(data.len()))
+    }
+
+    /// Makes sure some of the current segment's data is buffered
+    pub(crate) fn poll_fill_segment(&mut self, cx: &mut Context<'_>, poll_read: PollRead<R>) -> Poll<Result<()>> {
+        if self.decoder.buffer().is_empty() && ready!(self.poll_fill(cx, poll_read))? == 0 {
+            return Poll::Ready(Err(Error::TruncatedFrame));
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    /// Skips whatever is left of the current segment
+    fn poll_skip_segment(&mut self, cx: &mut Context<'_>, poll_read: PollRead<R>) -> Poll<Result<()>> {
+        while self.decoder.segment_remaining() > 0 {
+            ready!(self.poll_fill_segment(cx, poll_read))?;
+            self.decoder.take_segment_data(usize::MAX);
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    /// Skips whatever is left of a frame started with `poll_start_frame`, which can't be done when
+    /// its handle is dropped without blocking
+    fn poll_skip_frame(&mut self, cx: &mut Context<'_>, poll_read: PollRead<R>) -> Poll<Result<()>> {
+        while self.lazy {
+            ready!(self.poll_next_segment(cx, poll_read))?;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Implements the VarReader, FrameReader and SegmentReader shared by the tokio and futures-io readers,
+/// which use the `AsyncRead`, `AsyncWrite`, `AsyncWriteExt` and `poll_read` of the runtime in scope where the macro is used.
+/// * `runtime` names the streams in the docs, `module` is the module the readers are exported from, `block_on` opens
+///   the async block the examples run in, `read_ext` is the runtime's read extension trait and `select` its `select!` macro.
+macro_rules! var_reader {
+    (runtime: $runtime:literal, module: $module:literal, block_on: $block_on:literal, read_ext: $read_ext:literal, select: $select:literal $(,)?) => {
+        #[doc = concat!(" A reader that reads variable-length encoded data from a ", $runtime, " stream.")]
+        /// Frames are read in the default [`FrameFormat`](crate::FrameFormat) unless another format is set.
+        ///
+        /// Like the blocking reader, headers and small segments are served from an internal buffer.
+        /// * The reader may read past the end of a frame. Keep using the same reader for the whole stream,
+        ///   or recover the bytes it read ahead with [`VarReader::into_parts`].
+        /// # Example
+        /// ```
+        #[doc = concat!(" # ", $block_on)]
+        #[doc = concat!(" use send_it::", $module, "::VarReader;")]
+        ///
+        /// // Create a sample stream, this is the output from the above test_writer test
+        /// let stream: Vec<u8> = vec![21, 7, 0, 0, 0, 72, 101, 108, 108, 111, 44, 32, 6, 0, 0, 0, 87, 111, 114, 108, 100, 33];
+        /// // turn the vector into a slice as Vec does not implement Read
+        /// let mut fake_stream = stream.as_slice();
+        ///
+        /// // create a new VarReader
+        /// let mut reader = VarReader::new(&mut fake_stream);
+        ///
+        /// let data = reader.read_data().await.unwrap().unwrap();
+        /// assert_eq!(data[0].to_string(), "Hello, ");
+        /// assert_eq!(data[1].to_string(), "World!");
+        /// # });
+        /// ```
+        pub struct VarReader<'a, R: AsyncRead> {
+            core: ReaderCore<&'a mut R>,
+        }
+
+        impl<'a, R: AsyncRead + Unpin> VarReader<'a, R> {
+            /// Create a new VarReader with the default [`ReaderConfig`](crate::ReaderConfig) limits
+            pub fn new(reader: &'a mut R) -> Self {
+                Self::with_config(reader, $crate::ReaderConfig::default())
+            }
+
+            /// Create a new VarReader that enforces the given limits on incoming frames
+            pub fn with_config(reader: &'a mut R, config: $crate::ReaderConfig) -> Self {
+                let mut var_reader = Self::with_capacity(reader, $crate::reader::DEFAULT_BUFFER_CAPACITY);
+                var_reader.core.decoder.set_config(config);
+                var_reader
+            }
+
+            /// Create a new VarReader whose internal buffer holds `capacity` bytes.
+            /// * A capacity of 0 or 1 never reads past the end of a frame, at the cost of one read per header byte.
+            pub fn with_capacity(reader: &'a mut R, capacity: usize) -> Self {
+                VarReader { core: ReaderCore::new(reader, capacity) }
+            }
+
+            /// Returns the bytes read from the stream that have not been decoded yet
+            pub fn buffer(&self) -> &[u8] {
+                self.core.buffer()
+            }
+
+            /// Returns the capacity of the internal buffer
+            pub fn capacity(&self) -> usize {
+                self.core.capacity()
+            }
+
+            /// Unwraps the VarReader, returning the stream and the bytes read from it that have not been decoded yet
+            pub fn into_parts(self) -> (&'a mut R, Vec<u8>) {
+                self.core.into_parts()
+            }
+
+            /// Returns the limits this reader enforces
+            pub fn config(&self) -> &$crate::ReaderConfig {
+                self.core.decoder.config()
+            }
+
+            /// Replaces the limits this reader enforces
+            pub fn set_config(&mut self, config: $crate::ReaderConfig) {
+                self.core.decoder.set_config(config);
+            }
+
+            /// Returns the format frames are read in
+            pub fn format(&self) -> &$crate::FrameFormat {
+                self.core.decoder.format()
+            }
+
+            /// Sets the format frames are read in, which must match the peer's writer
+            pub fn set_format(&mut self, format: $crate::FrameFormat) {
+                self.core.decoder.set_format(format);
+            }
+
+            /// Returns the byte order fixed-width lengths are read in
+            pub fn byte_order(&self) -> $crate::ByteOrder {
+                self.core.decoder.byte_order()
+            }
+
+            /// Sets the byte order fixed-width lengths are read in, which must match the peer's writer
+            pub fn set_byte_order(&mut self, byte_order: $crate::ByteOrder) {
+                self.core.decoder.set_byte_order(byte_order);
+            }
+
+            /// Reads data from the stream.
+            /// * Returns `Ok(None)` if the stream ended cleanly between two frames.
+            /// * Returns [`Error::TruncatedFrame`](crate::Error::TruncatedFrame) if the stream ended in the middle of a frame.
+            ///
+            /// This method is cancel safe: if it is dropped before completing, for example because another branch of
+            #[doc = concat!(" `", $select, "` won, the partially read frame is kept in the reader and the next call picks up where it left off.")]
+            /// # Example
+            /// ```
+            #[doc = concat!(" # ", $block_on)]
+            #[doc = concat!(" use send_it::", $module, "::VarReader;")]
+            ///
+            /// let stream: Vec<u8> = vec![21, 7, 0, 0, 0, 72, 101, 108, 108, 111, 44, 32, 6, 0, 0, 0, 87, 111, 114, 108, 100, 33];
+            /// // turn the vector into a slice as Vec does not implement Read
+            /// let mut fake_stream = stream.as_slice();
+            ///
+            /// // create a new VarReader
+            /// let mut reader = VarReader::new(&mut fake_stream);
+            ///
+            /// let data = reader.read_data().await.unwrap().unwrap();
+            /// assert_eq!(data[0].to_string(), "Hello, ");
+            /// assert_eq!(data[1].to_string(), "World!");
+            /// # });
+            /// ```
+            pub async fn read_data(&mut self) -> $crate::Result<Option<Vec<$crate::Segment>>> {
+                ::std::future::poll_fn(|cx| self.core.poll_read_data(cx, poll_read)).await
+            }
+
+            /// Starts reading the next frame one segment at a time, without holding the whole frame in memory.
+            /// * Returns `Ok(None)` if the stream ended cleanly between two frames.
+            /// * The frame's segments are read with [`FrameReader::next_segment`]. Whatever is left of a frame or segment
+            ///   when its handle is dropped is skipped by the next call on the reader.
+            /// * Since the frame is never held in memory, its segments are checked against
+            ///   [`max_streamed_segment_size`](crate::ReaderConfig::max_streamed_segment_size) instead of `max_frame_size` and `max_segment_size`.
+            /// # Example
+            /// ```
+            #[doc = concat!(" # ", $block_on)]
+            #[doc = concat!(" use ", $read_ext, ";")]
+            #[doc = concat!(" use send_it::", $module, "::VarReader;")]
+            ///
+            /// let stream: Vec<u8> = vec![21, 7, 0, 0, 0, 72, 101, 108, 108, 111, 44, 32, 6, 0, 0, 0, 87, 111, 114, 108, 100, 33];
+            /// let mut fake_stream = stream.as_slice();
+            /// let mut reader = VarReader::new(&mut fake_stream);
+            ///
+            /// let mut frame = reader.next_frame().await.unwrap().unwrap();
+            /// let mut segment = frame.next_segment().await.unwrap().unwrap();
+            /// assert_eq!(segment.len(), 7);
+            ///
+            /// let mut hello = String::new();
+            /// segment.read_to_string(&mut hello).await.unwrap();
+            /// assert_eq!(hello, "Hello, ");
+            /// # });
+            /// ```
+            pub async fn next_frame(&mut self) -> $crate::Result<Option<FrameReader<'_, &'a mut R>>> {
+                let started = ::std::future::poll_fn(|cx| self.core.poll_start_frame(cx, poll_read)).await?;
+                Ok(started.then_some(FrameReader { core: &mut self.core, done: false }))
+            }
+        }
+
+        /// A frame whose segments are read one at a time, created by [`VarReader::next_frame`].
+        pub struct FrameReader<'r, R> {
+            core: &'r mut ReaderCore<R>,
+            done: bool,
+        }
+
+        impl<R: AsyncRead + Unpin> FrameReader<'_, R> {
+            /// Starts reading the next segment of the frame, skipping whatever is left of the previous one.
+            /// * Returns `Ok(None)` once every segment of the frame has been read.
+            pub async fn next_segment(&mut self) -> $crate::Result<Option<SegmentReader<'_, R>>> {
+                if self.done {
+                    return Ok(None);
+                }
+                match ::std::future::poll_fn(|cx| self.core.poll_next_segment(cx, poll_read)).await? {
+                    Some(len) => Ok(Some(SegmentReader { core: self.core, len })),
+                    None => {
+                        self.done = true;
+                        Ok(None)
+                    }
+                }
+            }
+        }
+
+        /// An [`AsyncRead`] over exactly the data of one segment, created by [`FrameReader::next_segment`].
+        /// * Reading past the end of a segment reads 0 bytes, and a stream ending in the middle of it fails with
+        ///   [`ErrorKind::UnexpectedEof`](std::io::ErrorKind::UnexpectedEof).
+        pub struct SegmentReader<'f, R> {
+            core: &'f mut ReaderCore<R>,
+            len: usize,
+        }
+
+        impl<R> SegmentReader<'_, R> {
+            /// Returns the size of the segment
+            pub fn len(&self) -> usize {
+                self.len
+            }
+
+            /// Returns true if the segment is empty
+            pub fn is_empty(&self) -> bool {
+                self.len == 0
+            }
+
+            /// Returns the number of bytes of the segment that have not been read yet
+            pub fn remaining(&self) -> usize {
+                self.core.decoder.segment_remaining()
+            }
+        }
+
+        impl<R: AsyncRead + Unpin> SegmentReader<'_, R> {
+            /// Copies whatever is left of the segment to `writer`, such as a file or a socket,
+            /// returning the number of bytes copied.
+            /// * The data goes through the reader's own buffer, so memory use is bounded by its capacity
+            ///   however large the segment is.
+            /// * Not cancel safe: data taken from the buffer but not yet written is lost if the future is dropped.
+            /// # Example
+            /// ```
+            #[doc = concat!(" # ", $block_on)]
+            #[doc = concat!(" use send_it::", $module, "::VarReader;")]
+            ///
+            /// let stream: Vec<u8> = vec![21, 7, 0, 0, 0, 72, 101, 108, 108, 111, 44, 32, 6, 0, 0, 0, 87, 111, 114, 108, 100, 33];
+            /// let mut fake_stream = stream.as_slice();
+            /// let mut reader = VarReader::new(&mut fake_stream);
+            ///
+            /// let mut frame = reader.next_frame().await.unwrap().unwrap();
+            /// let mut segment = frame.next_segment().await.unwrap().unwrap();
+            ///
+            /// let mut file = Vec::new();
+            /// assert_eq!(segment.read_into(&mut file).await.unwrap(), 7);
+            /// assert_eq!(file, b"Hello, ");
+            /// # });
+            /// ```
+            pub async fn read_into<W: AsyncWrite + Unpin + ?Sized>(&mut self, writer: &mut W) -> $crate::Result<u64> {
+                let mut copied = 0;
+                while self.remaining() > 0 {
+                    ::std::future::poll_fn(|cx| self.core.poll_fill_segment(cx, poll_read)).await?;
+                    let data = self.core.decoder.take_segment_data(usize::MAX);
+                    writer.write_all(data).await?;
+                    copied += data.len() as u64;
+                }
+                Ok(copied)
+            }
+        }
+    };
+}
+
+pub(crate) use var_reader;
@@ -0,0 +1,304 @@
+use std::future::poll_fn;
+use std::io::{self, ErrorKind, IoSlice};
+use std::pin::Pin;
+
+use crate::encode::FrameHeader;
+use crate::poll_io::{PollRead, PollWrite};
+use crate::{Error, FrameFormat, Result, Segment};
+
+/// The size of the buffer segments are copied through from their source
+const COPY_BUFFER_SIZE: usize = 8 * 1024;
+
+/// A segment of a frame being built by one of the writers of an async stream, whether tokio or futures-io.
+/// * `S` is the runtime's boxed read trait object.
+pub(crate) enum Part<S: ?Sized> {
+    /// A segment held in memory
+    Data(Segment),
+    /// A segment read from its source while the frame is sent
+    Reader { reader: Box<S>, len: usize },
+}
+
+impl<S: ?Sized> Part<S> {
+    fn len(&self) -> usize {
+        match self {
+            Part::Data(segment) => segment.len(),
+            Part::Reader { len, .. } => *len,
+        }
+    }
+}
+
+/// Encodes the parts as one frame and sends it over the stream
+pub(crate) async fn send_parts<S, W>(format: &FrameFormat, parts: &mut [Part<S>], stream: &mut W, poll_read: PollRead<S>, poll_write: PollWrite<W>) -> Result<()>
+where
+    S: Unpin + ?Sized,
+    W: Unpin,
+{
+    // encode every length prefix up front, checking they all fit before writing anything
+    let header = FrameHeader::encode(format, parts.iter().map(Part::len))?;
+    if parts.is_empty() {
+        return Ok(write_all_vectored(stream, &mut header.io_slices::<Segment>(&[]), poll_write).await?);
+    }
+
+    // in-memory segments are gathered into vectored writes, flushed whenever a reader comes up
+    let mut slices = Vec::new();
+    for (prefix, part) in header.prefixes().zip(parts) {
+        slices.push(IoSlice::new(prefix));
+        match part {
+            Part::Data(segment) => {
+                let segment: &Segment = segment;
+                if !segment.as_ref().is_empty() {
+                    slices.push(IoSlice::new(segment.as_ref()));
+                }
+            }
+            Part::Reader { reader, len } => {
+                write_all_vectored(stream, &mut slices, poll_write).await?;
+                slices.clear();
+                copy_segment(&mut **reader, *len, stream, poll_read, poll_write).await?;
+            }
+        }
+    }
+    write_all_vectored(stream, &mut slices, poll_write).await?;
+
+    Ok(())
+}
+
+/// Encodes the segments as one frame and sends it over the stream
+#[cfg(feature="reading")]
+pub(crate) async fn send_frame<W: Unpin>(format: &FrameFormat, segments: &[Segment], stream: &mut W, poll_write: PollWrite<W>) -> Result<()> {
+    // encode every length prefix up front, checking they all fit before writing anything
+    let header = FrameHeader::encode(format, segments.iter().map(Segment::len))?;
+    let mut slices = header.io_slices(segments);
+    write_all_vectored(stream, &mut slices, poll_write).await?;
+
+    Ok(())
+}
+
+/// Copies exactly `len` bytes from the reader to the stream
+async fn copy_segment<S, W>(reader: &mut S, len: usize, stream: &mut W, poll_read: PollRead<S>, poll_write: PollWrite<W>) -> Result<()>
+where
+    S: Unpin + ?Sized,
+    W: Unpin,
+{
+    let mut buf = vec![0; len.min(COPY_BUFFER_SIZE)];
+    let mut copied = 0;
+    while copied < len {
+        let limit = (len - copied).min(buf.len());
+        let read = match poll_fn(|cx| poll_read(Pin::new(&mut *reader), cx, &mut buf[..limit])).await {
+            Ok(0) => return Err(Error::SourceTooShort { declared: len, read: copied }),
+            Ok(read) => read,
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.into()),
+        };
+        write_all_vectored(stream, &mut [IoSlice::new(&buf[..read])], poll_write).await?;
+        copied += read;
+    }
+    Ok(())
+}
+
+/// Writes all of the slices, issuing as few writes as the stream allows
+async fn write_all_vectored<W: Unpin>(stream: &mut W, mut slices: &mut [IoSlice<'_>], poll_write: PollWrite<W>) -> io::Result<()> {
+    while !slices.is_empty() {
+        match poll_fn(|cx| poll_write(Pin::new(&mut *stream), cx, slices)).await {
+            Ok(0) => return Err(ErrorKind::WriteZero.into()),
+            Ok(written) => IoSlice::advance_slices(&mut slices, written),
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+/// Implements the VarWriter shared by the tokio and futures-io writers,
+/// which use the `AsyncRead`, `AsyncWrite`, `poll_read` and `poll_write_vectored` of the runtime in scope where the macro is used.
+/// * `runtime` names the streams in the docs, `module` is the module the writer is exported from, `block_on` opens
+///   the async block the examples run in and `cursor` is a cursor the runtime can read from.
+macro_rules! var_writer {
+    (runtime: $runtime:literal, module: $module:literal, block_on: $block_on:literal, cursor: $cursor:literal $(,)?) => {
+        #[doc = concat!(" A writer for sending several segments over a ", $runtime, " stream using variable length encoding")]
+        /// Frames are written in the default [`FrameFormat`](crate::FrameFormat) unless another format is set.
+        /// # Example
+        /// ```
+        #[doc = concat!(" # ", $block_on)]
+        #[doc = concat!(" use send_it::", $module, "::VarWriter;")]
+        ///
+        /// let mut sender = VarWriter::new();
+        ///
+        /// sender.add_string("Hello");
+        /// sender.add_string("World");
+        ///
+        /// let mut buffer = Vec::new();
+        /// sender.send(&mut buffer).await.unwrap();
+        /// # });
+        /// ```
+        pub struct VarWriter {
+            data: Vec<$crate::poll_writer::Part<Source>>,
+            format: $crate::FrameFormat,
+        }
+
+        /// The sources segments can be read from while a frame is sent
+        type Source = dyn AsyncRead + Send + Sync + Unpin;
+
+        impl VarWriter {
+            /// Create a new VarWriter
+            pub fn new() -> VarWriter {
+                Self::with_format($crate::FrameFormat::default())
+            }
+
+            /// Create a new VarWriter that writes fixed-width lengths in the given byte order
+            pub fn with_byte_order(byte_order: $crate::ByteOrder) -> VarWriter {
+                Self::with_format($crate::FrameFormat { byte_order, ..$crate::FrameFormat::default() })
+            }
+
+            /// Create a new VarWriter that writes frames in the given format
+            pub fn with_format(format: $crate::FrameFormat) -> VarWriter {
+                VarWriter {
+                    data: Vec::new(),
+                    format,
+                }
+            }
+
+            /// Returns the format frames are written in
+            pub fn format(&self) -> &$crate::FrameFormat {
+                &self.format
+            }
+
+            /// Sets the format frames are written in, which must match the peer's reader
+            pub fn set_format(&mut self, format: $crate::FrameFormat) {
+                self.format = format;
+            }
+
+            /// Returns the byte order fixed-width lengths are written in
+            pub fn byte_order(&self) -> $crate::ByteOrder {
+                self.format.byte_order
+            }
+
+            /// Sets the byte order fixed-width lengths are written in, which must match the peer's reader
+            pub fn set_byte_order(&mut self, byte_order: $crate::ByteOrder) {
+                self.format.byte_order = byte_order;
+            }
+
+            /// Add a segment to the writer
+            pub fn add(&mut self, segment: $crate::Segment) {
+                self.data.push($crate::poll_writer::Part::Data(segment));
+            }
+
+            /// Add a string to the writer
+            /// # Example
+            /// ```
+            #[doc = concat!(" use send_it::", $module, "::VarWriter;")]
+            ///
+            /// let mut sender = VarWriter::new();
+            ///
+            /// sender.add_string("Hello");
+            /// ```
+            pub fn add_string<S: Into<String>>(&mut self, string: S) {
+                self.add($crate::Segment::from(string.into()))
+            }
+
+            /// Add raw data to the writer
+            /// # Example
+            /// ```
+            #[doc = concat!(" use send_it::", $module, "::VarWriter;")]
+            ///
+            /// let mut sender = VarWriter::new();
+            ///
+            /// sender.add_raw(&[0x48, 0x65, 0x6C, 0x6C, 0x6F]);
+            /// ```
+            pub fn add_raw(&mut self, raw: &[u8]) {
+                self.add($crate::Segment::from(raw));
+            }
+
+            /// Add a segment of `len` bytes that is read from `reader` while the frame is sent,
+            /// instead of being held in memory.
+            /// * Only the first `len` bytes of the reader are sent, anything after them is left unread.
+            /// * Sending fails with [`Error::SourceTooShort`](crate::Error::SourceTooShort) if the reader ends before
+            ///   `len` bytes. The frame's header has already been written by then, so the stream should be closed.
+            /// * The reader is read each time the frame is sent, so a frame sent with `send_without_clearing`
+            ///   can only be sent again if the reader has `len` more bytes to give.
+            /// * The reader must be `Send` and `Sync`, like the rest of the VarWriter.
+            /// # Example
+            /// ```
+            #[doc = concat!(" # ", $block_on)]
+            #[doc = concat!(" use ", $cursor, ";")]
+            #[doc = concat!(" use send_it::", $module, "::VarWriter;")]
+            ///
+            /// let mut sender = VarWriter::new();
+            ///
+            /// sender.add_string("Hello, ");
+            /// sender.add_reader(Cursor::new(b"World!"), 6);
+            ///
+            /// let mut buffer = Vec::new();
+            /// sender.send(&mut buffer).await.unwrap();
+            /// assert_eq!(buffer.len(), 22);
+            /// # });
+            /// ```
+            pub fn add_reader<R: AsyncRead + Send + Sync + Unpin + 'static>(&mut self, reader: R, len: usize) {
+                self.data.push($crate::poll_writer::Part::Reader { reader: Box::new(reader), len });
+            }
+
+            /// Encodes the data and sends it over the stream.
+            /// * The data is cleared after sending.
+            /// * Fails with [`Error::LengthOverflow`](crate::Error::LengthOverflow) without writing anything if a segment
+            ///   is too long for the format's segment length prefix, such as a segment over 4 GiB with the default `u32` lengths.
+            ///   Use [`LengthPrefix::U64`](crate::LengthPrefix::U64) to send larger segments.
+            /// # Example
+            /// ```
+            #[doc = concat!(" # ", $block_on)]
+            #[doc = concat!(" use send_it::", $module, "::VarWriter;")]
+            ///
+            /// let mut sender = VarWriter::new();
+            ///
+            /// sender.add_string("Hello");
+            /// sender.add_string("World");
+            ///
+            /// let mut buffer = Vec::new();
+            /// sender.send(&mut buffer).await.unwrap();
+            /// # });
+            /// ```
+            pub async fn send<W: AsyncWrite + Unpin>(&mut self, stream: &mut W) -> $crate::Result<()> {
+                self.send_without_clearing(stream).await?;
+
+                // Clear the internal data after sending
+                self.clear();
+
+                Ok(())
+            }
+
+            /// Encodes the data and sends it over the stream.
+            /// * The data is not cleared after sending.
+            /// * Fails with [`Error::LengthOverflow`](crate::Error::LengthOverflow) without writing anything if a segment
+            ///   is too long for the format's segment length prefix, such as a segment over 4 GiB with the default `u32` lengths.
+            ///   Use [`LengthPrefix::U64`](crate::LengthPrefix::U64) to send larger segments.
+            /// # Example
+            /// ```
+            #[doc = concat!(" # ", $block_on)]
+            #[doc = concat!(" use send_it::", $module, "::VarWriter;")]
+            ///
+            /// let mut sender = VarWriter::new();
+            ///
+            /// sender.add_string("Hello");
+            /// sender.add_string("World");
+            ///
+            /// let mut buffer = Vec::new();
+            /// sender.send_without_clearing(&mut buffer).await.unwrap();
+            /// # });
+            /// ```
+            pub async fn send_without_clearing<W: AsyncWrite + Unpin>(&mut self, stream: &mut W) -> $crate::Result<()> {
+                $crate::poll_writer::send_parts(&self.format, &mut self.data, stream, poll_read, poll_write_vectored).await
+            }
+
+            /// Removes all segments from the writer
+            pub fn clear(&mut self) {
+                self.data.clear();
+            }
+        }
+
+        impl Default for VarWriter {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+    };
+}
+
+pub(crate) use var_writer;
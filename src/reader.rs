@@ -1,9 +1,19 @@
-use std::io::Read;
+use std::io::{ErrorKind, Read, Write};
+use std::iter::FusedIterator;
 
-use crate::Segment;
+use crate::decoder::{FrameDecoder, SegmentStart};
+use crate::{ByteOrder, Error, FrameBuf, FrameFormat, ReaderConfig, Result, Segment};
+
+/// The default capacity of a VarReader's internal buffer
+pub const DEFAULT_BUFFER_CAPACITY: usize = 8 * 1024;
 
 /// A reader that reads variable-length encoded data from a stream.
-/// Data is read in little-endian unless the big-endian feature is enabled.
+/// Frames are read in the default [`FrameFormat`] unless another format is set.
+///
+/// Headers and small segments are served from an internal buffer, while segments larger than the buffer
+/// are read directly into their destination.
+/// * The reader may read past the end of a frame. Keep using the same reader for the whole stream,
+///   or recover the bytes it read ahead with [`VarReader::into_parts`].
 /// # Example
 /// ```
 /// use send_it::reader::VarReader;
@@ -14,53 +24,111 @@ use crate::Segment;
 /// let mut fake_stream = stream.as_slice();
 ///
 /// // create a new VarReader
-/// let mut reader = crate::reader::VarReader::new(&mut fake_stream);
+/// let mut reader = VarReader::new(&mut fake_stream);
 ///
-/// let data = reader.read_data().unwrap();
-/// assert_eq!(data[0].to_string(), "Hello");
-/// assert_eq!(data[1].to_string(), "World");
+/// let data = reader.read_data().unwrap().unwrap();
+/// assert_eq!(data[0].to_string(), "Hello, ");
+/// assert_eq!(data[1].to_string(), "World!");
 /// ```
 pub struct VarReader<'a, R: Read> {
-    reader: &'a mut R,
+    core: ReaderCore<&'a mut R>,
 }
 
 impl<'a, R: Read> VarReader<'a, R> {
-    /// Create a new VarReader
+    /// Create a new VarReader with the default [`ReaderConfig`] limits
     pub fn new(reader: &'a mut R) -> Self {
-        VarReader { reader }
+        Self::with_config(reader, ReaderConfig::default())
     }
 
-    fn read_varint(&mut self) -> std::io::Result<usize> {
-        let mut value = 0usize;
-        let mut shift = 0;
-        loop {
-            let mut buf = [0; 1];
-            self.reader.read_exact(&mut buf)?;
-            let byte = buf[0];
-            value |= ((byte & 0x7F) as usize) << shift;
-            shift += 7;
-            if byte & 0x80 == 0 {
-                break;
-            }
-        }
-        Ok(value)
+    /// Create a new VarReader that enforces the given limits on incoming frames
+    /// # Example
+    /// ```
+    /// use send_it::reader::VarReader;
+    /// use send_it::ReaderConfig;
+    ///
+    /// // a frame header declaring a 4 GiB frame
+    /// let stream: Vec<u8> = vec![0x80, 0x80, 0x80, 0x80, 0x10];
+    /// let mut fake_stream = stream.as_slice();
+    ///
+    /// let config = ReaderConfig { max_frame_size: 1024, ..ReaderConfig::default() };
+    /// let mut reader = VarReader::with_config(&mut fake_stream, config);
+    ///
+    /// assert!(reader.read_data().is_err());
+    /// ```
+    pub fn with_config(reader: &'a mut R, config: ReaderConfig) -> Self {
+        let mut var_reader = Self::with_capacity(reader, DEFAULT_BUFFER_CAPACITY);
+        var_reader.core.decoder.set_config(config);
+        var_reader
+    }
+
+    /// Create a new VarReader whose internal buffer holds `capacity` bytes.
+    /// * A capacity of 0 or 1 never reads past the end of a frame, at the cost of one read per header byte.
+    /// # Example
+    /// ```
+    /// use send_it::reader::VarReader;
+    ///
+    /// let stream: Vec<u8> = vec![10, 6, 0, 0, 0, 87, 111, 114, 108, 100, 33, 0xFF];
+    /// let mut fake_stream = stream.as_slice();
+    ///
+    /// let mut reader = VarReader::with_capacity(&mut fake_stream, 64);
+    /// assert_eq!(reader.read_data().unwrap().unwrap()[0].to_string(), "World!");
+    ///
+    /// // the byte after the frame was read ahead into the buffer
+    /// let (_, leftover) = reader.into_parts();
+    /// assert_eq!(leftover, [0xFF]);
+    /// ```
+    pub fn with_capacity(reader: &'a mut R, capacity: usize) -> Self {
+        VarReader { core: ReaderCore::new(reader, capacity) }
     }
 
-    #[cfg(not(feature = "big-endian"))]
-    fn read_u32(&mut self) -> std::io::Result<u32> {
-        let mut bytes = [0; 4];
-        self.reader.read_exact(&mut bytes)?;
-        Ok(u32::from_le_bytes(bytes))
+    /// Returns the bytes read from the stream that have not been decoded yet
+    pub fn buffer(&self) -> &[u8] {
+        self.core.buffer()
     }
 
-    #[cfg(feature = "big-endian")]
-    fn read_u32(&mut self) -> std::io::Result<u32> {
-        let mut bytes = [0; 4];
-        self.reader.read_exact(&mut bytes)?;
-        Ok(u32::from_be_bytes(bytes))
+    /// Returns the capacity of the internal buffer
+    pub fn capacity(&self) -> usize {
+        self.core.capacity()
+    }
+
+    /// Unwraps the VarReader, returning the stream and the bytes read from it that have not been decoded yet
+    pub fn into_parts(self) -> (&'a mut R, Vec<u8>) {
+        self.core.into_parts()
+    }
+
+    /// Returns the limits this reader enforces
+    pub fn config(&self) -> &ReaderConfig {
+        self.core.decoder.config()
+    }
+
+    /// Replaces the limits this reader enforces
+    pub fn set_config(&mut self, config: ReaderConfig) {
+        self.core.decoder.set_config(config);
+    }
+
+    /// Returns the format frames are read in
+    pub fn format(&self) -> &FrameFormat {
+        self.core.decoder.format()
+    }
+
+    /// Sets the format frames are read in, which must match the peer's writer
+    pub fn set_format(&mut self, format: FrameFormat) {
+        self.core.decoder.set_format(format);
+    }
+
+    /// Returns the byte order fixed-width lengths are read in
+    pub fn byte_order(&self) -> ByteOrder {
+        self.core.decoder.byte_order()
+    }
+
+    /// Sets the byte order fixed-width lengths are read in, which must match the peer's writer
+    pub fn set_byte_order(&mut self, byte_order: ByteOrder) {
+        self.core.decoder.set_byte_order(byte_order);
     }
 
     /// Reads data from the stream.
+    /// * Returns `Ok(None)` if the stream ended cleanly between two frames.
+    /// * Returns [`Error::TruncatedFrame`] if the stream ended in the middle of a frame.
     /// # Example
     /// ```
     /// use send_it::reader::VarReader;
@@ -70,23 +138,446 @@ impl<'a, R: Read> VarReader<'a, R> {
     /// let mut fake_stream = stream.as_slice();
     ///
     /// // create a new VarReader
-    /// let mut reader = crate::reader::VarReader::new(&mut fake_stream);
+    /// let mut reader = VarReader::new(&mut fake_stream);
+    ///
+    /// let data = reader.read_data().unwrap().unwrap();
+    /// assert_eq!(data[0].to_string(), "Hello, ");
+    /// assert_eq!(data[1].to_string(), "World!");
+    /// ```
+    pub fn read_data(&mut self) -> Result<Option<Vec<Segment>>> {
+        self.core.read_data()
+    }
+
+    /// Returns an iterator over the frames of the stream, which ends when the stream ends cleanly between two frames.
+    /// * Yields an error if a frame is invalid or the stream ends in the middle of one, and ends after it.
+    /// # Example
+    /// ```
+    /// use send_it::reader::VarReader;
+    ///
+    /// let stream: Vec<u8> = vec![10, 6, 0, 0, 0, 87, 111, 114, 108, 100, 33, 10, 6, 0, 0, 0, 87, 111, 114, 108, 100, 33];
+    /// let mut fake_stream = stream.as_slice();
+    /// let mut reader = VarReader::new(&mut fake_stream);
+    ///
+    /// for frame in reader.frames() {
+    ///     assert_eq!(frame.unwrap()[0].to_string(), "World!");
+    /// }
+    /// ```
+    pub fn frames(&mut self) -> Frames<'_, 'a, R> {
+        Frames { reader: self, done: false }
+    }
+
+    /// Reads every frame until the stream ends, such as all the frames of a file.
+    /// * Fails if any frame is invalid or the stream ends in the middle of a frame.
+    /// # Example
+    /// ```
+    /// use send_it::reader::VarReader;
+    ///
+    /// let stream: Vec<u8> = vec![10, 6, 0, 0, 0, 87, 111, 114, 108, 100, 33, 10, 6, 0, 0, 0, 87, 111, 114, 108, 100, 33];
+    /// let mut fake_stream = stream.as_slice();
+    ///
+    /// let frames = VarReader::new(&mut fake_stream).read_all().unwrap();
+    /// assert_eq!(frames.len(), 2);
+    /// ```
+    pub fn read_all(&mut self) -> Result<Vec<Vec<Segment>>> {
+        self.frames().collect()
+    }
+
+    /// Starts reading the next frame one segment at a time, without holding the whole frame in memory.
+    /// * Returns `Ok(None)` if the stream ended cleanly between two frames.
+    /// * The frame's segments are read with [`FrameReader::next_segment`], and whatever is left of the frame
+    ///   is skipped when the FrameReader is dropped.
+    /// * Since the frame is never held in memory, its segments are checked against
+    ///   [`max_streamed_segment_size`](ReaderConfig::max_streamed_segment_size) instead of `max_frame_size` and `max_segment_size`.
+    /// # Example
+    /// ```
+    /// use std::io::Read;
+    /// use send_it::reader::VarReader;
+    ///
+    /// let stream: Vec<u8> = vec![21, 7, 0, 0, 0, 72, 101, 108, 108, 111, 44, 32, 6, 0, 0, 0, 87, 111, 114, 108, 100, 33];
+    /// let mut fake_stream = stream.as_slice();
+    /// let mut reader = VarReader::new(&mut fake_stream);
     ///
-    /// let data = reader.read_data().unwrap();
-    /// assert_eq!(data[0].to_string(), "Hello");
-    /// assert_eq!(data[1].to_string(), "World");
+    /// let mut frame = reader.next_frame().unwrap().unwrap();
+    /// let mut segment = frame.next_segment().unwrap().unwrap();
+    /// assert_eq!(segment.len(), 7);
+    ///
+    /// let mut hello = String::new();
+    /// segment.read_to_string(&mut hello).unwrap();
+    /// assert_eq!(hello, "Hello, ");
     /// ```
-    pub fn read_data(&mut self) -> std::io::Result<Vec<Segment>> {
-        let total_size = self.read_varint()?;
-        let mut data = Vec::new();
+    pub fn next_frame(&mut self) -> Result<Option<FrameReader<'_, &'a mut R>>> {
+        Ok(self.core.start_frame(true)?.then(|| FrameReader { core: &mut self.core, done: false }))
+    }
 
-        while data.iter().map(|segment: &Segment| segment.len() + 4).sum::<usize>() < total_size {
-            let segment_size = self.read_u32()? as usize;
-            let mut segment_data = vec![0u8; segment_size];
-            self.reader.read_exact(&mut segment_data)?;
-            data.push(Segment::from(segment_data));
+    /// Reads a frame into `frame`, replacing its previous contents, and returns true.
+    /// * Returns `Ok(false)` if the stream ended cleanly between two frames.
+    /// * Unlike [`VarReader::read_data`], which allocates every segment, this reuses the frame's buffer,
+    ///   so reading many frames into the same [`FrameBuf`] stops allocating once it fits the largest of them.
+    /// # Example
+    /// ```
+    /// use send_it::reader::VarReader;
+    /// use send_it::FrameBuf;
+    ///
+    /// let stream: Vec<u8> = vec![21, 7, 0, 0, 0, 72, 101, 108, 108, 111, 44, 32, 6, 0, 0, 0, 87, 111, 114, 108, 100, 33];
+    /// let mut fake_stream = stream.as_slice();
+    /// let mut reader = VarReader::new(&mut fake_stream);
+    ///
+    /// let mut frame = FrameBuf::new();
+    /// assert!(reader.read_data_into(&mut frame).unwrap());
+    /// assert_eq!(&frame[1], b"World!");
+    ///
+    /// assert!(!reader.read_data_into(&mut frame).unwrap());
+    /// ```
+    pub fn read_data_into(&mut self, frame: &mut FrameBuf) -> Result<bool> {
+        frame.clear();
+        // the segments are collected, so the frame limits apply as they do to read_data
+        if !self.core.start_frame(false)? {
+            return Ok(false);
+        }
+        while self.core.next_segment()?.is_some() {
+            self.core.read_segment_to_end(frame.data_mut())?;
+            frame.end_segment();
         }
+        Ok(true)
+    }
 
-        Ok(data)
+    /// Reads a frame, handing each of its segments to `f` with its index as they come off the stream
+    /// instead of collecting them, returning the number of segments in the frame.
+    /// * Returns `Ok(None)` if the stream ended cleanly between two frames.
+    /// * Whatever `f` leaves unread of a segment is skipped. If `f` fails, the rest of the frame is skipped
+    ///   and its error is returned.
+    /// # Example
+    /// ```
+    /// use send_it::reader::VarReader;
+    ///
+    /// let stream: Vec<u8> = vec![21, 7, 0, 0, 0, 72, 101, 108, 108, 111, 44, 32, 6, 0, 0, 0, 87, 111, 114, 108, 100, 33];
+    /// let mut fake_stream = stream.as_slice();
+    /// let mut reader = VarReader::new(&mut fake_stream);
+    ///
+    /// // stream each segment into its own file
+    /// let mut files = vec![Vec::new(), Vec::new()];
+    /// let count = reader.read_data_with(|index, segment| {
+    ///     segment.read_into(&mut files[index])?;
+    ///     Ok(())
+    /// }).unwrap();
+    ///
+    /// assert_eq!(count, Some(2));
+    /// assert_eq!(files[1], b"World!");
+    /// ```
+    pub fn read_data_with<F>(&mut self, mut f: F) -> Result<Option<usize>>
+    where
+        F: FnMut(usize, &mut SegmentReader<'_, &'a mut R>) -> Result<()>,
+    {
+        let Some(mut frame) = self.next_frame()? else {
+            return Ok(None);
+        };
+        let mut index = 0;
+        while let Some(mut segment) = frame.next_segment()? {
+            f(index, &mut segment)?;
+            index += 1;
+        }
+        Ok(Some(index))
     }
-}
\ No newline at end of file
+}
+
+/// A frame whose segments are read one at a time, created by [`VarReader::next_frame`].
+/// * Whatever is left of the frame is skipped when it is dropped.
+pub struct FrameReader<'r, R: Read> {
+    core: &'r mut ReaderCore<R>,
+    done: bool,
+}
+
+impl<R: Read> FrameReader<'_, R> {
+    /// Starts reading the next segment of the frame, skipping whatever is left of the previous one.
+    /// * Returns `Ok(None)` once every segment of the frame has been read.
+    pub fn next_segment(&mut self) -> Result<Option<SegmentReader<'_, R>>> {
+        if self.done {
+            return Ok(None);
+        }
+        match self.core.next_segment()? {
+            Some(len) => Ok(Some(SegmentReader { core: self.core, len })),
+            None => {
+                self.done = true;
+                Ok(None)
+            }
+        }
+    }
+}
+
+impl<R: Read> Drop for FrameReader<'_, R> {
+    fn drop(&mut self) {
+        // an error is reported by the reader's next call, which tries to skip the rest again
+        let _ = self.core.skip_frame();
+    }
+}
+
+/// A [`Read`] over exactly the data of one segment, created by [`FrameReader::next_segment`].
+/// * Reading past the end of a segment returns 0, and a stream ending in the middle of it fails with
+///   [`ErrorKind::UnexpectedEof`].
+/// * Whatever is left of the segment is skipped when it is dropped.
+pub struct SegmentReader<'f, R: Read> {
+    core: &'f mut ReaderCore<R>,
+    len: usize,
+}
+
+impl<R: Read> SegmentReader<'_, R> {
+    /// Returns the size of the segment
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the segment is empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of bytes of the segment that have not been read yet
+    pub fn remaining(&self) -> usize {
+        self.core.decoder.segment_remaining()
+    }
+
+    /// Copies whatever is left of the segment to `writer`, such as a file, a hasher or a socket,
+    /// returning the number of bytes copied.
+    /// * The data goes through the reader's own buffer, so memory use is bounded by its capacity
+    ///   however large the segment is.
+    /// # Example
+    /// ```
+    /// use send_it::reader::VarReader;
+    ///
+    /// let stream: Vec<u8> = vec![21, 7, 0, 0, 0, 72, 101, 108, 108, 111, 44, 32, 6, 0, 0, 0, 87, 111, 114, 108, 100, 33];
+    /// let mut fake_stream = stream.as_slice();
+    /// let mut reader = VarReader::new(&mut fake_stream);
+    ///
+    /// let mut frame = reader.next_frame().unwrap().unwrap();
+    /// let mut segment = frame.next_segment().unwrap().unwrap();
+    ///
+    /// let mut file = Vec::new();
+    /// assert_eq!(segment.read_into(&mut file).unwrap(), 7);
+    /// assert_eq!(file, b"Hello, ");
+    /// ```
+    pub fn read_into<W: Write + ?Sized>(&mut self, writer: &mut W) -> Result<u64> {
+        let mut copied = 0;
+        while self.remaining() > 0 {
+            self.core.fill_segment()?;
+            let data = self.core.decoder.take_segment_data(usize::MAX);
+            writer.write_all(data)?;
+            copied += data.len() as u64;
+        }
+        Ok(copied)
+    }
+}
+
+impl<R: Read> Read for SegmentReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        Ok(self.core.read_segment(buf)?)
+    }
+}
+
+impl<R: Read> Drop for SegmentReader<'_, R> {
+    fn drop(&mut self) {
+        // an error is reported by the frame's next call, which tries to skip the rest again
+        let _ = self.core.skip_segment();
+    }
+}
+
+/// An iterator over the frames of a stream, created by [`VarReader::frames`].
+pub struct Frames<'r, 'a, R: Read> {
+    reader: &'r mut VarReader<'a, R>,
+    done: bool,
+}
+
+impl<R: Read> Iterator for Frames<'_, '_, R> {
+    type Item = Result<Vec<Segment>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let frame = self.reader.read_data().transpose();
+        // the stream can't be resynchronised after an error
+        self.done = !matches!(frame, Some(Ok(_)));
+        frame
+    }
+}
+
+impl<R: Read> FusedIterator for Frames<'_, '_, R> {}
+
+/// The buffered decoding state shared by the readers that wrap a blocking stream.
+/// * All parsing is done by the [`FrameDecoder`], this only moves bytes from the stream into it.
+pub(crate) struct ReaderCore<R> {
+    pub(crate) reader: R,
+    pub(crate) decoder: FrameDecoder,
+    capacity: usize,
+    /// Whether a frame is being read one segment at a time
+    lazy: bool,
+}
+
+impl<R> ReaderCore<R> {
+    pub(crate) fn new(reader: R, capacity: usize) -> Self {
+        ReaderCore { reader, decoder: FrameDecoder::new(), capacity: capacity.max(1), lazy: false }
+    }
+
+    pub(crate) fn buffer(&self) -> &[u8] {
+        self.decoder.buffer()
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub(crate) fn into_parts(self) -> (R, Vec<u8>) {
+        let leftover = self.buffer().to_vec();
+        (self.reader, leftover)
+    }
+}
+
+impl<R: Read> ReaderCore<R> {
+    pub(crate) fn read_data(&mut self) -> Result<Option<Vec<Segment>>> {
+        self.skip_frame()?;
+        loop {
+            if let Some(frame) = self.decoder.decode()? {
+                return Ok(Some(frame));
+            }
+            let read = match self.decoder.pending_segment() {
+                // large segments bypass the buffer
+                Some((segment, remaining)) if remaining >= self.capacity => {
+                    self.reader.by_ref().take(remaining as u64).read_to_end(segment)?
+                }
+                _ => self.fill()?,
+            };
+            if read == 0 {
+                self.decoder.finish()?;
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Reads more of the stream into the decoder's buffer, returning the number of bytes read
+    fn fill(&mut self) -> Result<usize> {
+        loop {
+            let buf = self.decoder.read_buf(self.capacity);
+            let len = buf.len().min(self.capacity);
+            match self.reader.read(&mut buf[..len]) {
+                Ok(read) => {
+                    self.decoder.commit(read);
+                    return Ok(read);
+                }
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+
+    /// Reads the length of the next frame so that its segments can be read one at a time,
+    /// returning false if the stream ended cleanly before it.
+    /// * A `streamed` frame is never held in memory, so it is bound by `max_streamed_segment_size` instead of the frame limits.
+    pub(crate) fn start_frame(&mut self, streamed: bool) -> Result<bool> {
+        self.skip_frame()?;
+        loop {
+            if self.decoder.decode_frame_start(streamed)? {
+                self.lazy = true;
+                return Ok(true);
+            }
+            if self.fill()? == 0 {
+                self.decoder.finish()?;
+                return Ok(false);
+            }
+        }
+    }
+
+    /// Reads the length of the next segment of a frame started with `start_frame`,
+    /// skipping whatever is left of the previous one
+    pub(crate) fn next_segment(&mut self) -> Result<Option<usize>> {
+        self.skip_segment()?;
+        loop {
+            match self.decoder.decode_segment_start()? {
+                Some(SegmentStart::Segment(size)) => return Ok(Some(size)),
+                Some(SegmentStart::FrameEnd) => {
+                    self.lazy = false;
+                    return Ok(None);
+                }
+                None if self.fill()? == 0 => return Err(Error::TruncatedFrame),
+                None => {}
+            }
+        }
+    }
+
+    /// Reads the data of the current segment into `buf`, returning 0 once all of it has been read
+    pub(crate) fn read_segment(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let max = buf.len().min(self.decoder.segment_remaining());
+        if max == 0 {
+            return Ok(0);
+        }
+        if self.decoder.buffer().is_empty() {
+            if max >= self.capacity {
+                // large reads bypass the buffer
+                loop {
+                    match self.reader.read(&mut buf[..max]) {
+                        Ok(0) => return Err(Error::TruncatedFrame),
+                        Ok(read) => {
+                            self.decoder.consume_segment_data(read);
+                            return Ok(read);
+                        }
+                        Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                        Err(err) => return Err(err.into()),
+                    }
+                }
+            }
+            if self.fill()? == 0 {
+                return Err(Error::TruncatedFrame);
+            }
+        }
+        let data = self.decoder.take_segment_data(max);
+        buf[..data.len()].copy_from_slice(data);
+        Ok(data.len())
+    }
+
+    /// Makes sure some of the current segment's data is buffered
+    pub(crate) fn fill_segment(&mut self) -> Result<()> {
+        if self.decoder.buffer().is_empty() && self.fill()? == 0 {
+            return Err(Error::TruncatedFrame);
+        }
+        Ok(())
+    }
+
+    /// Appends whatever is left of the current segment to `data`
+    pub(crate) fn read_segment_to_end(&mut self, data: &mut Vec<u8>) -> Result<()> {
+        while self.decoder.segment_remaining() > 0 {
+            let remaining = self.decoder.segment_remaining();
+            if self.decoder.buffer().is_empty() && remaining >= self.capacity {
+                // large segments bypass the buffer, growing with the data received so far.
+                // Every chunk is consumed as soon as it is read, so that an error part way through
+                // leaves the decoder in step with the stream.
+                let start = data.len();
+                data.resize(start + remaining.min(start.max(self.capacity)), 0);
+                let result = self.reader.read(&mut data[start..]);
+                data.truncate(start + *result.as_ref().unwrap_or(&0));
+                match result {
+                    Ok(0) => return Err(Error::TruncatedFrame),
+                    Ok(read) => self.decoder.consume_segment_data(read),
+                    Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                    Err(err) => return Err(err.into()),
+                }
+            } else {
+                self.fill_segment()?;
+                data.extend_from_slice(self.decoder.take_segment_data(usize::MAX));
+            }
+        }
+        Ok(())
+    }
+
+    /// Skips whatever is left of the current segment
+    pub(crate) fn skip_segment(&mut self) -> Result<()> {
+        while self.decoder.segment_remaining() > 0 {
+            self.fill_segment()?;
+            self.decoder.take_segment_data(usize::MAX);
+        }
+        Ok(())
+    }
+
+    /// Skips whatever is left of a frame started with `start_frame`
+    pub(crate) fn skip_frame(&mut self) -> Result<()> {
+        while self.lazy && self.next_segment()?.is_some() {}
+        Ok(())
+    }
+}
@@ -1,9 +1,13 @@
-use std::io::Write;
-use crate::Segment;
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{ErrorKind, IoSlice, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use crate::encode::FrameHeader;
+use crate::{ByteOrder, Error, FrameFormat, Result, Segment};
 
 
 /// A writer for sending several segments over a stream using variable length encoding
-/// Data is written in little-endian if the feature "big-endian" is not enabled
+/// Frames are written in the default [`FrameFormat`] unless another format is set.
 /// # Example
 /// ```
 /// use send_it::writer::VarWriter;
@@ -17,20 +21,77 @@ use crate::Segment;
 /// sender.send(&mut buffer).unwrap();
 /// ```
 pub struct VarWriter {
-    data: Vec<Segment>,
+    data: Vec<Part>,
+    format: FrameFormat,
+}
+
+/// A segment of the frame being built
+enum Part {
+    /// A segment held in memory
+    Data(Segment),
+    /// A segment read from its source while the frame is sent
+    Reader { reader: Box<dyn Read + Send + Sync>, len: usize },
+    /// A range of a file copied to the stream while the frame is sent
+    File { file: File, range: Range<u64> },
+}
+
+impl Part {
+    /// Returns the length of the segment, failing if a file range is reversed or too long to address
+    fn len(&self) -> Result<usize> {
+        match self {
+            Part::Data(segment) => Ok(segment.len()),
+            Part::Reader { len, .. } => Ok(*len),
+            Part::File { range, .. } => {
+                let length = range.end.checked_sub(range.start)
+                    .ok_or(Error::InvalidRange { start: range.start, end: range.end })?;
+                usize::try_from(length).map_err(|_| Error::LengthOverflow { length, max: usize::MAX as u64 })
+            }
+        }
+    }
 }
 
 impl VarWriter {
     /// Create a new VarWriter
     pub fn new() -> VarWriter {
+        Self::with_format(FrameFormat::default())
+    }
+
+    /// Create a new VarWriter that writes fixed-width lengths in the given byte order
+    pub fn with_byte_order(byte_order: ByteOrder) -> VarWriter {
+        Self::with_format(FrameFormat { byte_order, ..FrameFormat::default() })
+    }
+
+    /// Create a new VarWriter that writes frames in the given format
+    pub fn with_format(format: FrameFormat) -> VarWriter {
         VarWriter {
             data: Vec::new(),
+            format,
         }
     }
 
+    /// Returns the format frames are written in
+    pub fn format(&self) -> &FrameFormat {
+        &self.format
+    }
+
+    /// Sets the format frames are written in, which must match the peer's reader
+    pub fn set_format(&mut self, format: FrameFormat) {
+        self.format = format;
+    }
+
+    /// Returns the byte order fixed-width lengths are written in
+    pub fn byte_order(&self) -> ByteOrder {
+        self.format.byte_order
+    }
+
+    /// Sets the byte order fixed-width lengths are written in, which must match the peer's reader
+    pub fn set_byte_order(&mut self, byte_order: ByteOrder) {
+        self.format.byte_order = byte_order;
+    }
+
     /// Add a segment to the writer
     pub fn add(&mut self, segment: Segment) {
-        self.data.push(segment);
+        self.data.push(Part::Data(segment));
     }
 
     /// Add a string to the writer
@@ -56,11 +117,67 @@ impl VarWriter {
     /// sender.add_raw(&[0x48, 0x65, 0x6C, 0x6C, 0x6F]);
     /// ```
     pub fn add_raw(&mut self, raw: &[u8]) {
-        self.data.push(Segment::from(raw));
+        self.add(Segment::from(raw));
+    }
+
+    /// Add a segment of `len` bytes that is read from `reader` while the frame is sent,
+    /// instead of being held in memory.
+    /// * Only the first `len` bytes of the reader are sent, anything after them is left unread.
+    /// * Sending fails with [`Error::SourceTooShort`] if the reader ends before `len` bytes.
+    ///   The frame's header has already been written by then, so the stream should be closed.
+    /// * The reader is read each time the frame is sent, so a frame sent with `send_without_clearing`
+    ///   can only be sent again if the reader has `len` more bytes to give.
+    /// * The reader must be `Send` and `Sync`, like the rest of the VarWriter.
+    /// # Example
+    /// ```
+    /// use std::io::Cursor;
+    /// use send_it::writer::VarWriter;
+    ///
+    /// let mut sender = VarWriter::new();
+    ///
+    /// sender.add_string("Hello, ");
+    /// sender.add_reader(Cursor::new(b"World!"), 6);
+    ///
+    /// let mut buffer = Vec::new();
+    /// sender.send(&mut buffer).unwrap();
+    /// assert_eq!(buffer.len(), 22);
+    /// ```
+    pub fn add_reader<R: Read + Send + Sync + 'static>(&mut self, reader: R, len: usize) {
+        self.data.push(Part::Reader { reader: Box::new(reader), len });
+    }
+
+    /// Add a segment holding the given byte range of a file, copied to the stream while the frame is sent.
+    /// * On Linux the bytes are moved by the kernel with `copy_file_range`, `sendfile` or `splice` when the
+    ///   stream is a file, a `TcpStream`, a `UnixStream` or a pipe, without going through userspace.
+    ///   Other streams and platforms fall back to a buffered copy.
+    /// * The file is read from `range.start` each time the frame is sent, moving its cursor.
+    /// * Sending fails with [`Error::InvalidRange`] without writing anything if `range.end` is before `range.start`.
+    /// * Sending fails with [`Error::SourceTooShort`] if the file ends before `range.end`.
+    ///   The frame's header has already been written by then, so the stream should be closed.
+    /// # Example
+    /// ```
+    /// use std::fs::File;
+    /// use send_it::writer::VarWriter;
+    ///
+    /// let file = File::open("Cargo.toml").unwrap();
+    /// let len = file.metadata().unwrap().len();
+    ///
+    /// let mut sender = VarWriter::new();
+    /// sender.add_string("Cargo.toml");
+    /// sender.add_file(file, 0..len);
+    ///
+    /// let mut buffer = Vec::new();
+    /// sender.send(&mut buffer).unwrap();
+    /// ```
+    pub fn add_file(&mut self, file: File, range: Range<u64>) {
+        self.data.push(Part::File { file, range });
     }
 
     /// Encodes the data and sends it over the stream.
     /// * The data is cleared after sending.
+    /// * Fails with [`Error::LengthOverflow`] without writing anything if a segment is too long for the
+    ///   format's segment length prefix, such as a segment over 4 GiB with the default `u32` lengths.
+    ///   Use [`LengthPrefix::U64`](crate::LengthPrefix::U64) to send larger segments.
     /// # Example
     /// ```
     /// use send_it::writer::VarWriter;
@@ -73,7 +190,7 @@ impl VarWriter {
     /// let mut buffer = Vec::new();
     /// sender.send(&mut buffer).unwrap();
     /// ```
-    pub fn send<W: Write>(&mut self, stream: &mut W) -> std::io::Result<()> {
+    pub fn send<W: Write>(&mut self, stream: &mut W) -> Result<()> {
         self.send_without_clearing(stream)?;
 
         // Clear the internal data after sending
@@ -84,6 +201,9 @@ impl VarWriter {
 
     /// Encodes the data and sends it over the stream.
     /// * The data is not cleared after sending.
+    /// * Fails with [`Error::LengthOverflow`] without writing anything if a segment is too long for the
+    ///   format's segment length prefix, such as a segment over 4 GiB with the default `u32` lengths.
+    ///   Use [`LengthPrefix::U64`](crate::LengthPrefix::U64) to send larger segments.
     /// # Example
     /// ```
     /// use send_it::writer::VarWriter;
@@ -96,58 +216,41 @@ impl VarWriter {
     /// let mut buffer = Vec::new();
     /// sender.send_without_clearing(&mut buffer).unwrap();
     /// ```
-    pub fn send_without_clearing<W: Write>(&mut self, stream: &mut W) -> std::io::Result<()> {
-        let total_size: usize = self.data.iter().map(|segment| segment.len() + 4).sum();
-
-        // Write the total size as varint
-        self.write_varint(stream, total_size)?;
-
-        // Write each segment's size and the segment itself
-        for segment in &self.data {
-            self.write_u32(stream, segment.len() as u32)?;
-            // write the segment
-            stream.write_all(segment.as_ref())?;
+    pub fn send_without_clearing<W: Write>(&mut self, stream: &mut W) -> Result<()> {
+        // encode every length prefix up front, checking they all fit before writing anything
+        let lengths = self.data.iter().map(Part::len).collect::<Result<Vec<_>>>()?;
+        let header = FrameHeader::encode(&self.format, lengths.iter().copied())?;
+        if self.data.is_empty() {
+            return Ok(write_all_vectored(stream, &mut header.io_slices::<Segment>(&[]))?);
         }
 
-        Ok(())
-    }
-    
-    fn write_varint<W: Write>(&self, writer: &mut W, mut value: usize) -> std::io::Result<()> {
-        loop {
-            let mut byte = (value & 0x7F) as u8;
-            value >>= 7;
-            if value != 0 {
-                byte |= 0x80;
-            }
-            writer.write_all(&[byte])?;
-            if value == 0 {
-                break;
+        // in-memory segments are gathered into vectored writes, flushed whenever a reader comes up
+        let mut slices = Vec::new();
+        for ((prefix, part), &len) in header.prefixes().zip(&mut self.data).zip(&lengths) {
+            slices.push(IoSlice::new(prefix));
+            match part {
+                Part::Data(segment) => {
+                    let segment: &Segment = segment;
+                    if !segment.as_ref().is_empty() {
+                        slices.push(IoSlice::new(segment.as_ref()));
+                    }
+                }
+                Part::Reader { reader, .. } => {
+                    write_all_vectored(stream, &mut slices)?;
+                    slices.clear();
+                    copy_segment(reader, len, stream)?;
+                }
+                Part::File { file, range } => {
+                    write_all_vectored(stream, &mut slices)?;
+                    slices.clear();
+                    file.seek(SeekFrom::Start(range.start))?;
+                    // `io::copy` uses the kernel's zero-copy paths for a `Take<&mut File>` on Linux
+                    copy_segment(file, len, stream)?;
+                }
             }
         }
-        Ok(())
-    }
-
-    #[cfg(not(feature = "big-endian"))]
-    fn write_u32<W: Write>(&self, writer: &mut W, value: u32) -> std::io::Result<()> {
-        // writes little-endian
-        writer.write_all(&[
-            (value & 0xFF) as u8,
-            ((value >> 8) & 0xFF) as u8,
-            ((value >> 16) & 0xFF) as u8,
-            ((value >> 24) & 0xFF) as u8,
-        ])?;
-        Ok(())
-    }
+        write_all_vectored(stream, &mut slices)?;
 
-    #[cfg(feature = "big-endian")]
-    fn write_u32<W: Write>(&self, writer: &mut W, value: u32) -> std::io::Result<()> {
-        // writes big-endian
-        writer.write_all(&[
-            ((value >> 24) & 0xFF) as u8,
-            ((value >> 16) & 0xFF) as u8,
-            ((value >> 8) & 0xFF) as u8,
-            (value & 0xFF) as u8,
-        ])?;
         Ok(())
     }
 
@@ -161,7 +264,7 @@ impl Write for VarWriter {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
         let vec = buf.to_vec();
         let size = vec.len();
-        self.data.push(Segment::from(vec));
+        self.add(Segment::from(vec));
         Ok(size)
     }
 
@@ -175,4 +278,117 @@ impl Default for VarWriter {
     fn default() -> Self {
         Self::new()
     }
-}
\ No newline at end of file
+}
+
+/// A frame of segments borrowed from existing buffers, which are written to the stream without being copied.
+/// Unlike VarWriter, which owns a copy of every segment, a BorrowedFrame only holds references to them,
+/// or owns the ones that were handed over as a `Vec<u8>`.
+/// # Example
+/// ```
+/// use send_it::writer::BorrowedFrame;
+///
+/// let header = [1, 2, 3];
+/// let payload = vec![0xAB; 1024];
+///
+/// let mut frame = BorrowedFrame::new();
+/// frame.add(&header);
+/// frame.add(payload.as_slice());
+/// frame.add_str("trailer");
+///
+/// let mut buffer = Vec::new();
+/// frame.send(&mut buffer).unwrap();
+/// assert_eq!(buffer.len(), 2 + 4 + 3 + 4 + 1024 + 4 + 7);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct BorrowedFrame<'a> {
+    segments: Vec<Cow<'a, [u8]>>,
+    format: FrameFormat,
+}
+
+impl<'a> BorrowedFrame<'a> {
+    /// Create a new, empty BorrowedFrame written in the default [`FrameFormat`]
+    pub fn new() -> Self {
+        Self::with_format(FrameFormat::default())
+    }
+
+    /// Create a new, empty BorrowedFrame written in the given format
+    pub fn with_format(format: FrameFormat) -> Self {
+        BorrowedFrame { segments: Vec::new(), format }
+    }
+
+    /// Returns the format the frame is written in
+    pub fn format(&self) -> &FrameFormat {
+        &self.format
+    }
+
+    /// Sets the format the frame is written in, which must match the peer's reader
+    pub fn set_format(&mut self, format: FrameFormat) {
+        self.format = format;
+    }
+
+    /// Add a segment to the frame: a borrowed `&[u8]` is referenced and an owned `Vec<u8>` is moved in,
+    /// neither is copied
+    pub fn add<S: Into<Cow<'a, [u8]>>>(&mut self, segment: S) {
+        self.segments.push(segment.into());
+    }
+
+    /// Add a borrowed string to the frame
+    pub fn add_str(&mut self, string: &'a str) {
+        self.add(string.as_bytes());
+    }
+
+    /// Returns the number of segments in the frame
+    pub fn len(&self) -> usize {
+        self.segments.len()
+    }
+
+    /// Returns true if the frame has no segments
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// Encodes the frame and sends it over the stream, in as few writes as the stream allows.
+    /// * The frame is kept, so it can be sent again.
+    /// * Fails with [`Error::LengthOverflow`] without writing anything if a segment is too long for the
+    ///   format's segment length prefix.
+    pub fn send<W: Write>(&self, stream: &mut W) -> Result<()> {
+        send_frame(&self.format, &self.segments, stream)
+    }
+
+    /// Removes all segments from the frame
+    pub fn clear(&mut self) {
+        self.segments.clear();
+    }
+}
+
+/// Encodes the segments as one frame and sends it over the stream
+pub(crate) fn send_frame<W: Write, S: AsRef<[u8]>>(format: &FrameFormat, segments: &[S], stream: &mut W) -> Result<()> {
+    // encode every length prefix up front, checking they all fit before writing anything
+    let header = FrameHeader::encode(format, segments.iter().map(|segment| segment.as_ref().len()))?;
+    let mut slices = header.io_slices(segments);
+    write_all_vectored(stream, &mut slices)?;
+
+    Ok(())
+}
+
+/// Copies exactly `len` bytes from the reader to the stream
+fn copy_segment<R: Read + ?Sized, W: Write>(reader: &mut R, len: usize, stream: &mut W) -> Result<()> {
+    let read = std::io::copy(&mut reader.take(len as u64), stream)?;
+    if read < len as u64 {
+        return Err(Error::SourceTooShort { declared: len, read: read as usize });
+    }
+    Ok(())
+}
+
+/// Writes all of the slices, issuing as few writes as the stream allows
+fn write_all_vectored<W: Write>(stream: &mut W, mut slices: &mut [IoSlice<'_>]) -> std::io::Result<()> {
+    while !slices.is_empty() {
+        match stream.write_vectored(slices) {
+            Ok(0) => return Err(ErrorKind::WriteZero.into()),
+            Ok(written) => IoSlice::advance_slices(&mut slices, written),
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}